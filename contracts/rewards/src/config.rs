@@ -1,22 +1,28 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Api, StdResult, Storage};
+use cosmwasm_std::{Addr, Api, Decimal, StdResult, Storage};
+use cw_rewards_logic::underlying::UnderlyingSource;
 use cw_storage_plus::Item;
 
 use crate::msg::{
     ConfigUpdate, DistributionConfig, IncentiveConfig, InflationConfig, InstantiateMsg,
-    StakingConfig, UnderlyingConfig,
+    ModuleUpdate, StakeLimits, StakingConfig, UnderlyingConfig,
 };
 
 use super::ContractError;
 
 #[cw_serde]
 pub struct Config {
-    pub owner: Addr,
+    /// `None` once ownership is renounced.
+    pub owner: Option<Addr>,
     pub staking_module: StakingConfig,
     pub incentive_module: Option<IncentiveConfig>,
     pub distribution_module: Option<DistributionConfig>,
     pub underlying_rewards_module: Option<UnderlyingConfig>,
     pub inflation_module: Option<InflationConfig>,
+    #[serde(default)]
+    pub stake_limits: Option<StakeLimits>,
+    #[serde(default)]
+    pub forfeit_recipient: Option<Addr>,
 }
 
 impl Config {
@@ -30,16 +36,170 @@ impl Config {
     }
 
     pub fn validate(&self, api: &dyn Api) -> Result<(), ContractError> {
-        api.addr_validate(self.owner.as_str())?;
+        if let Some(owner) = &self.owner {
+            api.addr_validate(owner.as_str())?;
+        }
+        if let Some(recipient) = &self.forfeit_recipient {
+            api.addr_validate(recipient.as_str())?;
+        }
+        if let Some(distribution) = &self.distribution_module {
+            let schedules = std::iter::once(&distribution.fees)
+                .chain(distribution.denom_fees.iter().map(|(_, fees)| fees));
+            for fees in schedules {
+                validate_fees(api, fees.iter().map(|(rate, r)| (rate, r.addr())))?;
+            }
+        }
+        let reward_fees = [
+            self.incentive_module.as_ref().map(|cfg| &cfg.reward_fees),
+            self.underlying_rewards_module
+                .as_ref()
+                .map(|cfg| &cfg.reward_fees),
+            self.inflation_module.as_ref().map(|cfg| &cfg.reward_fees),
+        ];
+        for fees in reward_fees.into_iter().flatten() {
+            validate_fees(api, fees.iter().map(|(rate, addr)| (rate, addr)))?;
+        }
+
+        if let Some(conversion) = self
+            .distribution_module
+            .as_ref()
+            .and_then(|cfg| cfg.conversion.as_ref())
+        {
+            for route in conversion.routes.iter() {
+                api.addr_validate(route.contract.as_str())?;
+                if !conversion.target_denoms.contains(&route.ask_denom)
+                    || route.max_spread > Decimal::one()
+                {
+                    return Err(ContractError::InvalidSwapRoute(route.offer_denom.clone()));
+                }
+            }
+        }
+
+        if let Some(stream) = self
+            .distribution_module
+            .as_ref()
+            .and_then(|cfg| cfg.stream.as_ref())
+        {
+            if stream.duration == 0 {
+                return Err(ContractError::InvalidStream {});
+            }
+        }
+
+        if let Some(top_up) = self
+            .distribution_module
+            .as_ref()
+            .and_then(|cfg| cfg.inflation_top_up.as_ref())
+        {
+            if top_up.distribution_share > Decimal::one()
+                || top_up.incentive_fee_share > Decimal::one()
+            {
+                return Err(ContractError::InvalidInflationTopUp {});
+            }
+        }
+
+        if let StakingConfig::Delegated { validators, .. } = &self.staking_module {
+            let total: Decimal = validators.iter().map(|(_, weight)| weight).sum();
+            let unique = validators
+                .iter()
+                .enumerate()
+                .all(|(i, (v, _))| validators[..i].iter().all(|(other, _)| other != v));
+            if total != Decimal::one() || !unique {
+                return Err(ContractError::InvalidValidatorWeights {});
+            }
+        }
+
+        if let StakingConfig::NativeTokens { denoms } = &self.staking_module {
+            let unique = denoms
+                .iter()
+                .enumerate()
+                .all(|(i, (d, _))| denoms[..i].iter().all(|(other, _)| other != d));
+            let weighted = denoms.iter().all(|(_, multiplier)| !multiplier.is_zero());
+            if denoms.is_empty() || !unique || !weighted {
+                return Err(ContractError::InvalidStakeDenoms {});
+            }
+        }
+
+        if let Some(limits) = &self.stake_limits {
+            let StakingConfig::NativeToken { receipt, .. } = &self.staking_module else {
+                return Err(ContractError::InvalidStakingConfig(
+                    "StakeLimits",
+                    self.staking_module.clone(),
+                ));
+            };
+            // Receipts can be released and passed on, so stake can't be attributed to a staker
+            if receipt.is_some() && limits.max_per_staker.is_some() {
+                return Err(ContractError::StakerCapWithReceipts {});
+            }
+            let caps = [limits.max_per_staker, limits.max_total];
+            if caps
+                .iter()
+                .flatten()
+                .any(|cap| Some(*cap) < limits.min_stake)
+            {
+                return Err(ContractError::InvalidStakeLimits {});
+            }
+        }
+
+        if let Some(underlying) = &self.underlying_rewards_module {
+            if let Some(forward_to) = &underlying.forward_stake_to {
+                if !matches!(self.staking_module, StakingConfig::NativeToken { .. }) {
+                    return Err(ContractError::InvalidStakingConfig(
+                        "NativeToken",
+                        self.staking_module.clone(),
+                    ));
+                }
+                let source = UnderlyingSource::CwRewards {
+                    contract: forward_to.clone(),
+                };
+                if !underlying.sources.contains(&source) {
+                    return Err(ContractError::InvalidStakeForwarding {});
+                }
+            }
+        }
 
         Ok(())
     }
 
-    pub fn apply_update(&mut self, msg: ConfigUpdate) -> Result<(), ContractError> {
-        if let Some(owner) = msg.owner {
-            self.owner = owner;
+    pub fn is_owner(&self, addr: &Addr) -> bool {
+        self.owner.as_ref() == Some(addr)
+    }
+
+    /// Whether `msg` only changes the incentive whitelist, min size and fee, which incentive moderators
+    /// can do.
+    pub fn is_incentive_moderation(&self, msg: &ConfigUpdate) -> bool {
+        let (
+            Some(current),
+            Some(ModuleUpdate {
+                update: Some(update),
+            }),
+        ) = (&self.incentive_module, &msg.incentive_cfg)
+        else {
+            return false;
+        };
+        let incentive_only = ConfigUpdate {
+            incentive_cfg: msg.incentive_cfg.clone(),
+            ..Default::default()
+        };
+        *msg == incentive_only
+            && update.crank_limit == current.crank_limit
+            && update.reward_fees == current.reward_fees
+    }
+
+    /// The crank limit and reward fees for released incentives, if either incentives or streamed
+    /// distributions are enabled.
+    pub fn incentive_crank(&self) -> Option<(usize, &[(Decimal, Addr)])> {
+        let stream = self
+            .distribution_module
+            .as_ref()
+            .and_then(|cfg| cfg.stream.as_ref());
+        match (&self.incentive_module, stream) {
+            (Some(incentive), _) => Some((incentive.crank_limit, &incentive.reward_fees)),
+            (None, Some(stream)) => Some((stream.crank_limit, &[])),
+            (None, None) => None,
         }
+    }
 
+    pub fn apply_update(&mut self, msg: ConfigUpdate) -> Result<(), ContractError> {
         if let Some(update) = msg.staking_cfg {
             self.staking_module = update.update;
         }
@@ -55,20 +215,46 @@ impl Config {
         if let Some(update) = msg.inflation_cfg {
             self.inflation_module = update.update;
         }
+        if let Some(update) = msg.stake_limits_cfg {
+            self.stake_limits = update.update;
+        }
+        if let Some(update) = msg.forfeit_recipient_cfg {
+            self.forfeit_recipient = update.update;
+        }
 
         Ok(())
     }
 }
 
+/// Fails unless each fee goes to a valid address, and the rates take at most the whole amount.
+fn validate_fees<'a>(
+    api: &dyn Api,
+    fees: impl Iterator<Item = (&'a Decimal, &'a Addr)>,
+) -> Result<(), ContractError> {
+    let mut total = Decimal::zero();
+    for (rate, addr) in fees {
+        api.addr_validate(addr.as_str())?;
+        total = total
+            .checked_add(*rate)
+            .map_err(|_| ContractError::InvalidFees {})?;
+    }
+    if total > Decimal::one() {
+        return Err(ContractError::InvalidFees {});
+    }
+    Ok(())
+}
+
 impl From<InstantiateMsg> for Config {
     fn from(msg: InstantiateMsg) -> Self {
         Self {
-            owner: msg.owner,
+            owner: Some(msg.owner),
             staking_module: msg.staking_module,
             incentive_module: msg.incentive_module,
             distribution_module: msg.distribution_module,
             underlying_rewards_module: msg.underlying_rewards_module,
             inflation_module: msg.inflation_module,
+            stake_limits: msg.stake_limits,
+            forfeit_recipient: msg.forfeit_recipient,
         }
     }
 }
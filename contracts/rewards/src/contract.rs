@@ -1,19 +1,27 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    ensure, ensure_eq, to_json_binary, wasm_execute, BankMsg, Binary, Deps, DepsMut, Env, Event,
-    MessageInfo, Response, SubMsg, Timestamp,
+    coins, ensure, ensure_eq, to_json_binary, BankMsg, Binary, Deps, DepsMut, Env, Event,
+    MessageInfo, Reply, ReplyOn, Response, Timestamp,
 };
 use cw2::set_contract_version;
 use cw4::MemberDiff;
 use cw_utils::{one_coin, NativeBalance};
+use kujira::KujiraMsg;
 
 use crate::migration::MigrateMsg;
 use crate::msg::*;
-use cw_rewards_logic::{incentive, inflation, RewardsSM};
-use cw_rewards_logic::{ClaimRewardsMsg, PendingRewardsResponse, RewardsMsg};
-
-use crate::{execute, query, Config, ContractError};
+use cw_rewards_logic::RewardsMsg;
+use cw_rewards_logic::{fee, incentive, inflation, util::calculate_share, RewardsSM};
+
+use crate::{
+    conversion, delegation, execute, hook, managers, ownership, pause, query, receipt, roles,
+    slash,
+    snapshot::{self, Measured},
+    transition, underlying,
+    weights::{self, Op},
+    Config, ContractError,
+};
 
 pub const CONTRACT_NAME: &str = "entropic/cw-rewards";
 pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -21,7 +29,11 @@ pub const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const STATE_MACHINE: RewardsSM = RewardsSM::new();
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(deps: DepsMut, env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+pub fn migrate(
+    deps: DepsMut,
+    env: Env,
+    msg: MigrateMsg,
+) -> Result<Response<KujiraMsg>, ContractError> {
     crate::migration::do_migrate(deps, env, msg)
 }
 
@@ -31,130 +43,70 @@ pub fn instantiate(
     env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
-) -> Result<Response, ContractError> {
-    mod weights {
-        use cosmwasm_schema::cw_serde;
-        use cosmwasm_std::Uint128;
-
-        #[cw_serde]
-        pub enum DaoDaoQueryMsg {
-            ListStakers {
-                start_after: Option<String>,
-                limit: Option<u32>,
-            },
-        }
-
-        #[cw_serde]
-        pub struct ListStakersResponse {
-            pub stakers: Vec<StakerBalanceResponse>,
-        }
-
-        #[cw_serde]
-        pub struct StakerBalanceResponse {
-            pub address: String,
-            pub balance: Uint128,
-        }
-    }
+) -> Result<Response<KujiraMsg>, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     let config = Config::from(msg);
     config.save(deps.storage, deps.api)?;
 
     STATE_MACHINE.initialize(deps.storage)?;
+    let create_receipt = receipt::subdenom(&config.staking_module).map(receipt::create);
 
-    if let StakingConfig::DaoDaoHook { daodao_addr } = config.staking_module {
-        let mut weights: Vec<weights::StakerBalanceResponse> = vec![];
-        let mut list_stakers: weights::ListStakersResponse;
-        loop {
-            let start_after = weights.last().map(|w| w.address.clone());
-            list_stakers = deps.querier.query_wasm_smart(
-                &daodao_addr,
-                &weights::DaoDaoQueryMsg::ListStakers {
-                    start_after,
-                    limit: Some(30), // Max is 30
-                },
-            )?;
-            if list_stakers.stakers.is_empty() {
-                break;
-            }
-
-            weights.extend(list_stakers.stakers);
-        }
-
-        for staker in weights {
-            STATE_MACHINE.set_weight(deps.storage, &staker.address, staker.balance, false)?;
-        }
-    }
+    hook::backfill(deps.storage, &deps.querier, &config.staking_module)?;
 
     if config.inflation_module.is_some() {
         inflation::LAST_INFLATION_UPDATE.save(deps.storage, &env.block.time)?;
     }
 
-    Ok(Response::default())
+    Ok(Response::default().add_messages(create_receipt))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
-) -> Result<Response, ContractError> {
+) -> Result<Response<KujiraMsg>, ContractError> {
     let mut config = Config::load(deps.storage)?;
     match msg {
         ExecuteMsg::Rewards(msg) => {
+            pause::ensure_rewards_active(deps.storage, &msg)?;
             let zero_staked = STATE_MACHINE.total_staked(deps.storage)?.is_zero();
-            if config.incentive_module.is_some() && !zero_staked {
-                let incentive_cfg = config.incentive_module.as_ref().unwrap();
-                incentive::distribute_lri(
+            if let (Some((crank_limit, fees)), false) = (config.incentive_crank(), zero_staked) {
+                incentive::distribute_lri_with_fees(
                     deps.storage,
-                    incentive_cfg.crank_limit,
+                    crank_limit,
                     STATE_MACHINE,
                     &env.block.time,
+                    fees,
                 )?;
             }
 
             let mut claim_underlying_msgs = vec![];
-            if let Some(underlying_rewards) = &config.underlying_rewards_module {
-                let pending: PendingRewardsResponse = deps.querier.query_wasm_smart(
-                    &underlying_rewards.underlying_rewards_contract,
-                    &QueryMsg::PendingRewards {
-                        staker: env.contract.address.clone(),
-                    },
-                )?;
-                if !pending.rewards.is_empty() && !zero_staked {
-                    STATE_MACHINE.distribute_rewards(deps.storage, &pending.rewards)?;
-                    claim_underlying_msgs.push(wasm_execute(
-                        &underlying_rewards.underlying_rewards_contract,
-                        &ExecuteMsg::Rewards(ClaimRewardsMsg { callback: None }.into()),
-                        vec![],
-                    )?);
-                }
+            if !zero_staked {
+                let sources = underlying::sources(&deps.querier, &env, &config)?;
+                claim_underlying_msgs = underlying::claim(&mut deps, &env, &sources)?;
             }
 
             if let Some(inflation) = &config.inflation_module {
-                inflation::crank(
+                let staked = slash::staked(deps.storage)?;
+                inflation::crank_with_fees(
                     deps.storage,
                     STATE_MACHINE,
+                    staked,
                     &inflation.rate_per_year,
                     &env.block.time,
+                    &inflation.reward_fees,
                 )?;
             }
 
-            let res = match msg {
-                RewardsMsg::Stake(msg) => execute::stake(deps, info, config, msg),
-                RewardsMsg::Unstake(msg) => execute::unstake(deps, info, config, msg),
-                RewardsMsg::ClaimRewards(msg) => execute::claim(deps, info, msg),
-                RewardsMsg::DistributeRewards(msg) => execute::distribute(deps, info, config, msg),
-            };
+            // Underlying rewards are only distributed once their claims are measured, so the message waits
+            if !claim_underlying_msgs.is_empty() {
+                underlying::defer(deps.storage, &info, &msg)?;
+                return Ok(Response::default().add_submessages(claim_underlying_msgs));
+            }
 
-            res.map(|mut res| {
-                res.messages = [
-                    claim_underlying_msgs.into_iter().map(SubMsg::new).collect(),
-                    res.messages,
-                ]
-                .concat();
-                res
-            })
+            execute_rewards(deps, env, info, config, msg)
         }
 
         // Weight change hook from DAODAO
@@ -169,6 +121,16 @@ pub fn execute(
                 }
             };
             ensure_eq!(info.sender, src_addr, ContractError::Unauthorized {});
+            // Changes are relative, so they're skipped for stakers not yet synced with the source
+            let staker = match &msg {
+                StakeChangedHookMsg::Stake { addr, .. }
+                | StakeChangedHookMsg::Unstake { addr, .. } => addr,
+            };
+            if !hook::is_synced(deps.storage, staker.as_str())? {
+                return Ok(Response::default().add_event(
+                    Event::new("rewards/update-weight-hook").add_attribute("skipped", staker),
+                ));
+            }
             match msg {
                 StakeChangedHookMsg::Stake { addr, amount } => {
                     STATE_MACHINE.increase_weight(
@@ -214,6 +176,7 @@ pub fn execute(
                 .add_event(Event::new("rewards/update-weights-hook").add_attributes(attrs)))
         }
         ExecuteMsg::AddIncentive { denom, schedule } => {
+            pause::ensure_active(deps.storage, "AddIncentive", |f| f.add_incentive)?;
             if config.incentive_module.is_none() {
                 return Err(ContractError::IncentivesNotEnabled {});
             }
@@ -221,7 +184,26 @@ pub fn execute(
 
             let mut sent = NativeBalance(info.funds);
             if let Some(fee) = incentive_cfg.fee.clone() {
-                sent = (sent - fee).map_err(|_| ContractError::InvalidIncentive {})?;
+                sent = (sent - fee.clone()).map_err(|_| ContractError::InvalidIncentive {})?;
+
+                // Route part of the fee into the inflation pool, if configured
+                let top_up = config
+                    .distribution_module
+                    .as_ref()
+                    .and_then(|cfg| cfg.inflation_top_up.as_ref())
+                    .filter(|_| config.inflation_module.is_some());
+                if let Some(top_up) = top_up {
+                    // A denom the pool doesn't hold is left out, rather than blocking incentives
+                    if inflation::accepts(deps.storage, &top_up.denom)? {
+                        if let Some(funds) = calculate_share(
+                            &mut vec![fee],
+                            &top_up.denom,
+                            top_up.incentive_fee_share,
+                        ) {
+                            inflation::fund(deps.storage, funds)?;
+                        }
+                    }
+                }
             }
             let sent = sent.into_vec();
 
@@ -243,14 +225,21 @@ pub fn execute(
                 &Timestamp::from_nanos(0),
             )?;
             if let Some(coin) = incentive.distribute(&env.block.time) {
-                STATE_MACHINE.distribute_rewards(deps.storage, &vec![coin])?;
+                let mut rewards = vec![coin];
+                fee::accrue(deps.storage, &mut rewards, &incentive_cfg.reward_fees)?;
+                STATE_MACHINE.distribute_rewards(deps.storage, &rewards)?;
             }
             incentive.save(deps.storage)?;
 
             Ok(Response::default())
         }
         ExecuteMsg::FundInflation {} => {
-            ensure!(info.sender == config.owner, ContractError::Unauthorized {});
+            roles::ensure_any(
+                deps.storage,
+                &config,
+                &[Role::InflationTreasurer],
+                &info.sender,
+            )?;
             ensure!(
                 config.inflation_module.is_some(),
                 ContractError::InflationNotEnabled {}
@@ -261,7 +250,12 @@ pub fn execute(
             Ok(Response::default())
         }
         ExecuteMsg::WithdrawInflation { amount } => {
-            ensure!(info.sender == config.owner, ContractError::Unauthorized {});
+            roles::ensure_any(
+                deps.storage,
+                &config,
+                &[Role::InflationTreasurer],
+                &info.sender,
+            )?;
             ensure!(
                 config.inflation_module.is_some(),
                 ContractError::InflationNotEnabled {}
@@ -281,19 +275,258 @@ pub fn execute(
             Ok(Response::default().add_message(withdraw_msg))
         }
         ExecuteMsg::AdjustWeights { delta } => {
+            weights::execute(deps, info, config, "AdjustWeights", Op::Set, delta)
+        }
+        ExecuteMsg::SetWeights { weights } => {
+            weights::execute(deps, info, config, "SetWeights", Op::Set, weights)
+        }
+        ExecuteMsg::IncreaseWeights { weights } => {
+            weights::execute(deps, info, config, "IncreaseWeights", Op::Increase, weights)
+        }
+        ExecuteMsg::DecreaseWeights { weights } => {
+            weights::execute(deps, info, config, "DecreaseWeights", Op::Decrease, weights)
+        }
+        ExecuteMsg::RegisterWeightManager { manager, quota } => {
+            roles::ensure_any(deps.storage, &config, &[Role::WeightAdmin], &info.sender)?;
+            let manager = deps.api.addr_validate(manager.as_str())?;
+            for staker in quota.stakers.iter().flatten() {
+                deps.api.addr_validate(staker.as_str())?;
+            }
+            managers::register(deps.storage, &manager, quota)?;
+
+            let event = Event::new("rewards/register-weight-manager")
+                .add_attributes(vec![("manager", manager.as_str())]);
+            Ok(Response::default().add_event(event))
+        }
+        ExecuteMsg::RemoveWeightManager { manager } => {
+            roles::ensure_any(deps.storage, &config, &[Role::WeightAdmin], &info.sender)?;
+            managers::remove(deps.storage, &manager)?;
+
+            let event = Event::new("rewards/remove-weight-manager")
+                .add_attributes(vec![("manager", manager.as_str())]);
+            Ok(Response::default().add_event(event))
+        }
+        ExecuteMsg::Slash { ratio, recipient } => {
             ensure!(
-                matches!(config.staking_module, StakingConfig::Permissioned {}),
-                ContractError::InvalidStakingConfig("AdjustWeights", config.staking_module)
+                slash::can_slash(deps.storage, &info.sender, config.owner.as_ref())?,
+                ContractError::Unauthorized {}
             );
-            ensure!(info.sender == config.owner, ContractError::Unauthorized {});
-            for (addr, weight) in delta {
-                STATE_MACHINE.set_weight(deps.storage, &addr.to_string(), weight, false)?;
-            }
+            let StakingConfig::NativeToken { denom, .. } = &config.staking_module else {
+                return Err(ContractError::InvalidStakingConfig(
+                    "Slash",
+                    config.staking_module,
+                ));
+            };
+            let forwarded = config
+                .underlying_rewards_module
+                .as_ref()
+                .and_then(|cfg| cfg.forward_stake_to.as_ref())
+                .is_some();
+            ensure!(!forwarded, ContractError::SlashForwardedStake {});
+            let recipient = deps.api.addr_validate(recipient.as_str())?;
+            let slashed = slash::slash(deps.storage, ratio)?;
+
+            let event = Event::new("rewards/slash").add_attributes(vec![
+                ("ratio", ratio.to_string()),
+                ("amount", slashed.to_string()),
+                ("recipient", recipient.to_string()),
+            ]);
+            Ok(Response::default()
+                .add_message(BankMsg::Send {
+                    to_address: recipient.to_string(),
+                    amount: coins(slashed.u128(), denom),
+                })
+                .add_event(event))
+        }
+        ExecuteMsg::SetSlasher { slasher } => {
+            ensure!(
+                config.is_owner(&info.sender),
+                ContractError::Unauthorized {}
+            );
+            let slasher = slasher
+                .map(|s| deps.api.addr_validate(s.as_str()))
+                .transpose()?;
+            slash::set_slasher(deps.storage, slasher.clone())?;
+
+            let event = Event::new("rewards/set-slasher").add_attributes(vec![(
+                "slasher",
+                slasher.map(|s| s.to_string()).unwrap_or_default(),
+            )]);
+            Ok(Response::default().add_event(event))
+        }
+        ExecuteMsg::SetPaused(flags) => {
+            ensure!(
+                pause::can_pause(deps.storage, &config, &info.sender)?,
+                ContractError::Unauthorized {}
+            );
+            pause::set_flags(deps.storage, &flags)?;
+
+            let event = Event::new("rewards/set-paused").add_attributes(vec![
+                ("sender", info.sender.to_string()),
+                ("stake", flags.stake.to_string()),
+                ("unstake", flags.unstake.to_string()),
+                ("claim", flags.claim.to_string()),
+                ("distribute", flags.distribute.to_string()),
+                ("add_incentive", flags.add_incentive.to_string()),
+            ]);
+            Ok(Response::default().add_event(event))
+        }
+        ExecuteMsg::SetGuardian { guardian } => {
+            roles::ensure_any(deps.storage, &config, &[Role::Pauser], &info.sender)?;
+            let guardian = guardian
+                .map(|g| deps.api.addr_validate(g.as_str()))
+                .transpose()?;
+            pause::set_guardian(deps.storage, guardian.clone())?;
+
+            let event = Event::new("rewards/set-guardian").add_attributes(vec![(
+                "guardian",
+                guardian.map(|g| g.to_string()).unwrap_or_default(),
+            )]);
+            Ok(Response::default().add_event(event))
+        }
+        ExecuteMsg::EmergencyUnstake {} => execute::emergency_unstake(deps, env, info, config),
+        ExecuteMsg::ClaimFees {} => {
+            let fees = fee::claim(deps.storage, &info.sender)?;
+            ensure!(!fees.is_empty(), ContractError::NoFeesToClaim {});
+
+            let event = Event::new("rewards/claim-fees")
+                .add_attributes(vec![("recipient", info.sender.as_str())]);
+            let claim_msg = BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: fees,
+            };
 
-            Ok(Response::default())
+            Ok(Response::default().add_message(claim_msg).add_event(event))
+        }
+        ExecuteMsg::WithdrawReceipts { amount } => {
+            execute::withdraw_receipts(deps, env, info, config, amount)
+        }
+        ExecuteMsg::DepositReceipts {} => execute::deposit_receipts(deps, env, info, config),
+        ExecuteMsg::ResyncWeights { start_after, limit } => {
+            ensure!(
+                hook::is_hook(&config.staking_module),
+                ContractError::InvalidStakingConfig("ResyncWeights", config.staking_module)
+            );
+            let status = hook::resync(
+                deps.storage,
+                &deps.querier,
+                &config.staking_module,
+                start_after,
+                limit,
+            )?;
+
+            let event = Event::new("rewards/resync-weights").add_attributes(vec![
+                ("synced", status.synced.to_string()),
+                ("cursor", status.cursor.unwrap_or_default()),
+                ("in_progress", status.in_progress.to_string()),
+            ]);
+            Ok(Response::default().add_event(event))
+        }
+        ExecuteMsg::WithdrawUnbonded { batch } => {
+            execute::withdraw_unbonded(deps, env, info, config, batch)
+        }
+        ExecuteMsg::SubmitUnbonding {} => execute::submit_unbonding(deps, env, config),
+        ExecuteMsg::RebalanceDelegations { validators } => {
+            let denom = match &mut config.staking_module {
+                StakingConfig::Delegated {
+                    denom,
+                    validators: current,
+                    ..
+                } => {
+                    *current = validators.clone();
+                    denom.clone()
+                }
+                _ => {
+                    return Err(ContractError::InvalidStakingConfig(
+                        "RebalanceDelegations",
+                        config.staking_module,
+                    ))
+                }
+            };
+            roles::ensure_any(deps.storage, &config, &[Role::ConfigAdmin], &info.sender)?;
+            config.save(deps.storage, deps.api)?;
+
+            // Redelegating withdraws pending staking rewards, so they're claimed and measured first
+            let sources = delegation::reward_sources(&deps.querier, &env.contract.address, &denom)?;
+            let claims = underlying::claim(&mut deps, &env, &sources)?;
+            let redelegations =
+                delegation::rebalance(&deps.querier, &env.contract.address, &denom, &validators)?;
+
+            let event = Event::new("rewards/rebalance-delegations")
+                .add_attribute("redelegations", redelegations.len().to_string());
+            Ok(Response::default()
+                .add_submessages(claims)
+                .add_messages(redelegations)
+                .add_event(event))
+        }
+        ExecuteMsg::GrantRole { role, addr } => {
+            ensure!(
+                config.is_owner(&info.sender),
+                ContractError::Unauthorized {}
+            );
+            let addr = deps.api.addr_validate(addr.as_str())?;
+            roles::grant(deps.storage, role, &addr)?;
+
+            let event = Event::new("rewards/grant-role").add_attributes(vec![
+                ("role", format!("{role:?}")),
+                ("addr", addr.to_string()),
+            ]);
+            Ok(Response::default().add_event(event))
+        }
+        ExecuteMsg::RevokeRole { role, addr } => {
+            ensure!(
+                config.is_owner(&info.sender),
+                ContractError::Unauthorized {}
+            );
+            roles::revoke(deps.storage, role, &addr);
+
+            let event = Event::new("rewards/revoke-role").add_attributes(vec![
+                ("role", format!("{role:?}")),
+                ("addr", addr.to_string()),
+            ]);
+            Ok(Response::default().add_event(event))
+        }
+        ExecuteMsg::UpdateOwnership(action) => {
+            let event = match action {
+                OwnershipAction::TransferOwnership { new_owner, expiry } => {
+                    ensure!(
+                        config.is_owner(&info.sender),
+                        ContractError::Unauthorized {}
+                    );
+                    let new_owner = deps.api.addr_validate(&new_owner)?;
+                    ownership::transfer(deps.storage, &env.block, new_owner.clone(), expiry)?;
+                    Event::new("rewards/transfer-ownership").add_attributes(vec![
+                        ("pending_owner", new_owner.to_string()),
+                        ("expiry", expiry.map(|e| e.to_string()).unwrap_or_default()),
+                    ])
+                }
+                OwnershipAction::AcceptOwnership => {
+                    ownership::accept(deps.storage, &env.block, &info.sender)?;
+                    config.owner = Some(info.sender.clone());
+                    config.save(deps.storage, deps.api)?;
+                    Event::new("rewards/accept-ownership")
+                        .add_attributes(vec![("owner", info.sender.as_str())])
+                }
+                OwnershipAction::RenounceOwnership => {
+                    ensure!(
+                        config.is_owner(&info.sender),
+                        ContractError::Unauthorized {}
+                    );
+                    ownership::clear(deps.storage);
+                    config.owner = None;
+                    config.save(deps.storage, deps.api)?;
+                    Event::new("rewards/renounce-ownership")
+                        .add_attributes(vec![("owner", info.sender.as_str())])
+                }
+            };
+            Ok(Response::default().add_event(event))
         }
         ExecuteMsg::UpdateConfig(msg) => {
-            ensure!(info.sender == config.owner, ContractError::Unauthorized {});
+            let allowed = match config.is_incentive_moderation(&msg) {
+                true => &[Role::ConfigAdmin, Role::IncentiveModerator][..],
+                false => &[Role::ConfigAdmin],
+            };
+            roles::ensure_any(deps.storage, &config, allowed, &info.sender)?;
             // If enabling inflation, set the last update time to now.
             if let (None, Some(ModuleUpdate { update: Some(_) })) =
                 (&config.inflation_module, &msg.inflation_cfg)
@@ -301,18 +534,146 @@ pub fn execute(
                 inflation::LAST_INFLATION_UPDATE.save(deps.storage, &env.block.time)?;
             }
 
+            // Receipts can be added, with the current stake minted into the contract's custody, but
+            // not changed or removed once they may be outstanding
+            let mut receipt_msgs = vec![];
+            if let Some(ModuleUpdate { update }) = &msg.staking_cfg {
+                match (
+                    receipt::subdenom(&config.staking_module),
+                    receipt::subdenom(update),
+                ) {
+                    (None, Some(receipt)) => {
+                        receipt_msgs.push(receipt::create(receipt));
+                        let staked = STATE_MACHINE.total_staked(deps.storage)?;
+                        if !staked.is_zero() {
+                            receipt_msgs.push(receipt::mint(
+                                &env,
+                                receipt,
+                                staked,
+                                &env.contract.address,
+                            ));
+                        }
+                    }
+                    (old, new) => ensure!(old == new, ContractError::ReceiptLocked {}),
+                }
+            }
+
+            // Forwarded stake lives in the underlying contract, so where stake goes can't change while
+            // there's any
+            if let Some(ModuleUpdate { update }) = &msg.underlying_cfg {
+                let forwarded = |cfg: &Option<UnderlyingConfig>| {
+                    cfg.as_ref().and_then(|cfg| cfg.forward_stake_to.clone())
+                };
+                ensure!(
+                    forwarded(&config.underlying_rewards_module) == forwarded(update)
+                        || slash::pooled(deps.storage)?.is_zero(),
+                    ContractError::ForwardStakeLocked {}
+                );
+            }
+
+            // Stake held under the current module is converted or refunded, and a new hook source
+            // resynced, so switching modules can't strand funds or leave stale weights
+            let mut migration = None;
+            if let Some(ModuleUpdate { update }) = &msg.staking_cfg {
+                if update != &config.staking_module {
+                    let msgs = transition::migrate(deps.storage, &config, update)?;
+                    let event = Event::new("rewards/migrate-staking").add_attributes(vec![
+                        ("from", transition::name(&config.staking_module)),
+                        ("to", transition::name(update)),
+                    ]);
+                    migration = Some((msgs, event));
+                }
+            }
+
             config.apply_update(msg)?;
             config.save(deps.storage, deps.api)?;
-            Ok(Response::default())
+            let mut res = Response::default().add_messages(receipt_msgs);
+            if let Some((msgs, event)) = migration {
+                hook::backfill(deps.storage, &deps.querier, &config.staking_module)?;
+                res = res.add_messages(msgs).add_event(event);
+            }
+            Ok(res)
         }
     }
 }
 
+fn execute_rewards(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    config: Config,
+    msg: RewardsMsg,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let res = match msg {
+        RewardsMsg::Stake(msg) => execute::stake(deps, env, info, config, msg),
+        RewardsMsg::Unstake(msg) => execute::unstake(deps, env, info, config, msg),
+        RewardsMsg::ClaimRewards(msg) => execute::claim(deps, info, msg),
+        RewardsMsg::DistributeRewards(msg) => execute::distribute(deps, env, info, config, msg),
+    };
+
+    res.map(|mut res| {
+        // Swaps are snapshotted as they're created, so they run before anything else moves funds
+        let (swaps, msgs): (Vec<_>, Vec<_>) = res
+            .messages
+            .into_iter()
+            .partition(|msg| msg.reply_on != ReplyOn::Never);
+        res.messages = [swaps, msgs].concat();
+        res
+    })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(
+    mut deps: DepsMut,
+    env: Env,
+    msg: Reply,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    match msg.id {
+        snapshot::MEASURED_REPLY_ID => match snapshot::settle(&mut deps, &env)? {
+            (Measured::Swap { .. }, proceeds) => {
+                conversion::distribute_proceeds(deps, env, proceeds)
+            }
+            (Measured::Claim { source, .. }, rewards) => {
+                let res = underlying::distribute_claimed(deps.branch(), source, rewards)?;
+                if !snapshot::is_settled(deps.storage)? {
+                    return Ok(res);
+                }
+
+                // Every claim has been distributed, so the deferred message can run
+                let Some((info, msg)) = underlying::take_deferred(deps.storage)? else {
+                    return Ok(res);
+                };
+                let config = Config::load(deps.storage)?;
+                let deferred = execute_rewards(deps, env, info, config, msg)?;
+                Ok(res
+                    .add_submessages(deferred.messages)
+                    .add_attributes(deferred.attributes)
+                    .add_events(deferred.events))
+            }
+        },
+        id => Err(ContractError::UnknownReply(id)),
+    }
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractError> {
     let config = Config::load(deps.storage)?;
     Ok(match msg {
         QueryMsg::Config {} => to_json_binary(&config),
+        QueryMsg::Ownership {} => to_json_binary(&ownership::query(deps.storage, &config)?),
+        QueryMsg::Roles { addr } => {
+            to_json_binary(&roles::query_roles(deps.storage, &config, addr)?)
+        }
+        QueryMsg::RoleMembers {
+            role,
+            start_after,
+            limit,
+        } => to_json_binary(&roles::query_members(
+            deps.storage,
+            role,
+            start_after,
+            limit,
+        )?),
         QueryMsg::PendingRewards { staker } => {
             to_json_binary(&query::pending_rewards(deps, env, &config, staker)?)
         }
@@ -324,5 +685,24 @@ pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> Result<Binary, ContractErro
             to_json_binary(&query::incentives(deps, start_after, limit)?)
         }
         QueryMsg::Inflation {} => to_json_binary(&query::inflation(deps, env, &config)?),
+        QueryMsg::InflationProjection {} => {
+            to_json_binary(&query::inflation_projection(deps, env, &config)?)
+        }
+        QueryMsg::AccruedFees { recipient } => {
+            to_json_binary(&query::accrued_fees(deps, recipient)?)
+        }
+        QueryMsg::WeightManager { manager } => {
+            to_json_binary(&managers::query_manager(deps.storage, manager)?)
+        }
+        QueryMsg::WeightManagers { start_after, limit } => {
+            to_json_binary(&managers::query_managers(deps.storage, start_after, limit)?)
+        }
+        QueryMsg::Pause {} => to_json_binary(&pause::query(deps.storage)?),
+        QueryMsg::Slashing {} => to_json_binary(&slash::query(deps.storage)?),
+        QueryMsg::ResyncStatus {} => to_json_binary(&hook::resync_status(deps.storage)?),
+        QueryMsg::Principal { staker } => to_json_binary(&query::principal(deps, staker)?),
+        QueryMsg::Unbonding { staker } => {
+            to_json_binary(&query::unbonding(deps, env, &config, staker)?)
+        }
     }?)
 }
@@ -0,0 +1,75 @@
+use cosmwasm_std::{wasm_execute, Coin, Decimal256, DepsMut, Env, Event, Response, SubMsg};
+use cw_rewards_logic::incentive;
+use cw_utils::NativeBalance;
+use kujira::{fin, KujiraMsg};
+
+use crate::{
+    contract::STATE_MACHINE,
+    msg::ConversionConfig,
+    snapshot::{self, Measured},
+    Config, ContractError,
+};
+
+/// Splits off the non-target denoms from `rewards`, returning the swaps that convert them.
+pub fn convert(
+    deps: &mut DepsMut,
+    env: &Env,
+    cfg: &ConversionConfig,
+    rewards: &mut Vec<Coin>,
+) -> Result<Vec<SubMsg<KujiraMsg>>, ContractError> {
+    let mut swaps = vec![];
+    for reward in rewards.iter() {
+        if cfg.target_denoms.contains(&reward.denom) {
+            continue;
+        }
+        let route = cfg
+            .routes
+            .iter()
+            .find(|r| r.offer_denom == reward.denom)
+            .ok_or_else(|| ContractError::NoSwapRoute(reward.denom.clone()))?;
+
+        let swap = wasm_execute(
+            &route.contract,
+            &fin::ExecuteMsg::Swap {
+                offer_asset: None,
+                belief_price: None,
+                max_spread: Some(Decimal256::from(route.max_spread)),
+                to: None,
+                callback: None,
+            },
+            vec![reward.clone()],
+        )?;
+        let measured = Measured::Swap {
+            ask_denom: route.ask_denom.clone(),
+        };
+        swaps.push(snapshot::measure(deps, env, measured, swap)?);
+    }
+    rewards.retain(|c| cfg.target_denoms.contains(&c.denom));
+
+    Ok(swaps)
+}
+
+/// Distributes, or streams, the measured proceeds of a swap.
+pub fn distribute_proceeds(
+    deps: DepsMut,
+    env: Env,
+    proceeds: Vec<Coin>,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    if !proceeds.is_empty() {
+        let stream = Config::load(deps.storage)?
+            .distribution_module
+            .and_then(|cfg| cfg.stream);
+        match stream {
+            Some(stream) => {
+                incentive::stream(deps.storage, &proceeds, &env.block.time, stream.duration)?
+            }
+            None => STATE_MACHINE.distribute_rewards(deps.storage, &proceeds)?,
+        }
+    }
+
+    let event = Event::new("rewards/swap-rewards").add_attributes(vec![
+        ("action", "rewards/swap-rewards"),
+        ("proceeds", &NativeBalance(proceeds).to_string()),
+    ]);
+    Ok(Response::default().add_event(event))
+}
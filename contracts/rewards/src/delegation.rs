@@ -0,0 +1,361 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    coin, ensure, Addr, CosmosMsg, Decimal, Order, QuerierWrapper, StakingMsg, StdError, StdResult,
+    Storage, Timestamp, Uint128,
+};
+use cw_rewards_logic::underlying::UnderlyingSource;
+use cw_storage_plus::{Item, Map};
+use kujira::KujiraMsg;
+
+use crate::{contract::STATE_MACHINE, msg::Unbonding, ContractError};
+
+/// The chain's limit on unbonding entries per delegator and validator.
+const MAX_UNBONDING_ENTRIES: u64 = 7;
+
+/// An epoch's unstakes, undelegated together so that each validator gets one unbonding entry per epoch.
+#[cw_serde]
+#[derive(Default)]
+pub struct Batch {
+    /// Stake weight unstaked into the batch. It keeps bearing slashes until the batch is undelegated.
+    pub shares: Uint128,
+    /// Tokens undelegated for the batch, once it's submitted.
+    pub amount: Uint128,
+    /// Tokens undelegated for this batch and every one before it. Batches unbond in order, so the
+    /// contract has to have received them all before this one is paid out.
+    pub undelegated_through: Uint128,
+    pub release_at: Option<Timestamp>,
+}
+
+const BATCHES: Map<u64, Batch> = Map::new("unbonding_batches");
+
+/// The batch taking new unstakes.
+const OPEN_BATCH: Item<u64> = Item::new("unbonding_open_batch");
+
+/// When the last batch was undelegated.
+const LAST_SUBMITTED: Item<Timestamp> = Item::new("unbonding_last_submitted");
+
+/// Tokens undelegated, and paid out to stakers, across all batches.
+const UNDELEGATED: Item<Uint128> = Item::new("unbonding_undelegated");
+const WITHDRAWN: Item<Uint128> = Item::new("unbonding_withdrawn");
+
+/// Each staker's shares of each batch.
+const CLAIMS: Map<(&Addr, u64), Uint128> = Map::new("unbonding_claims");
+
+/// Current delegations of `denom` from `delegator`, per validator.
+pub fn delegations(
+    querier: &QuerierWrapper,
+    delegator: &Addr,
+    denom: &str,
+) -> StdResult<Vec<(String, Uint128)>> {
+    Ok(querier
+        .query_all_delegations(delegator)?
+        .into_iter()
+        .filter(|d| d.amount.denom == denom && !d.amount.amount.is_zero())
+        .map(|d| (d.validator, d.amount.amount))
+        .collect())
+}
+
+/// Every current delegation as an underlying source, so its staking rewards are claimed and distributed.
+pub fn reward_sources(
+    querier: &QuerierWrapper,
+    delegator: &Addr,
+    denom: &str,
+) -> StdResult<Vec<UnderlyingSource>> {
+    Ok(delegations(querier, delegator, denom)?
+        .into_iter()
+        .map(|(validator, _)| UnderlyingSource::Delegation { validator })
+        .collect())
+}
+
+/// Splits `amount` across `validators` by weight, with any rounding remainder going to the first.
+fn split(validators: &[(String, Decimal)], amount: Uint128) -> Vec<(String, Uint128)> {
+    let mut split: Vec<_> = validators
+        .iter()
+        .map(|(validator, weight)| (validator.clone(), amount.mul_floor(*weight)))
+        .collect();
+    let allocated: Uint128 = split.iter().map(|(_, amount)| *amount).sum();
+    if let Some((_, first)) = split.first_mut() {
+        *first += amount - allocated;
+    }
+    split
+}
+
+pub fn delegate(
+    validators: &[(String, Decimal)],
+    denom: &str,
+    amount: Uint128,
+) -> Vec<CosmosMsg<KujiraMsg>> {
+    split(validators, amount)
+        .into_iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(validator, amount)| {
+            StakingMsg::Delegate {
+                validator,
+                amount: coin(amount.u128(), denom),
+            }
+            .into()
+        })
+        .collect()
+}
+
+/// Tokens delegated across all validators, net of slashes.
+fn delegated(querier: &QuerierWrapper, delegator: &Addr, denom: &str) -> StdResult<Uint128> {
+    Ok(delegations(querier, delegator, denom)?
+        .into_iter()
+        .map(|(_, amount)| amount)
+        .sum())
+}
+
+/// All stake weight the delegations back, including the open batch's.
+fn shares(storage: &dyn Storage) -> StdResult<Uint128> {
+    let open = BATCHES
+        .may_load(storage, OPEN_BATCH.may_load(storage)?.unwrap_or_default())?
+        .unwrap_or_default();
+    Ok(STATE_MACHINE
+        .total_staked(storage)?
+        .checked_add(open.shares)?)
+}
+
+/// The stake weight `amount` of newly delegated tokens buys. Stake weights are shares of the
+/// delegations, so a slash reduces every staker's stake at once.
+pub fn to_shares(
+    storage: &dyn Storage,
+    querier: &QuerierWrapper,
+    delegator: &Addr,
+    denom: &str,
+    amount: Uint128,
+) -> StdResult<Uint128> {
+    let shares = shares(storage)?;
+    if shares.is_zero() {
+        return Ok(amount);
+    }
+    amount
+        .checked_multiply_ratio(shares, delegated(querier, delegator, denom)?)
+        .map_err(|e| StdError::generic_err(e.to_string()))
+}
+
+/// The tokens `shares` of stake weight are worth.
+fn to_amount(
+    storage: &dyn Storage,
+    querier: &QuerierWrapper,
+    delegator: &Addr,
+    denom: &str,
+    shares: Uint128,
+) -> StdResult<Uint128> {
+    let total = self::shares(storage)?;
+    if total.is_zero() {
+        return Ok(Uint128::zero());
+    }
+    Ok(shares.multiply_ratio(delegated(querier, delegator, denom)?, total))
+}
+
+/// How long unstakes are batched for, so that no validator has more than the chain's limit of
+/// unbonding entries at once.
+pub fn epoch(unbonding_period: u64) -> u64 {
+    unbonding_period.saturating_add(MAX_UNBONDING_ENTRIES - 1) / MAX_UNBONDING_ENTRIES
+}
+
+/// Adds `shares` unstaked by `staker` to the open batch, returning its id. The shares must already be
+/// taken off the staker's weight.
+pub fn queue(storage: &mut dyn Storage, staker: &Addr, shares: Uint128) -> StdResult<u64> {
+    let id = OPEN_BATCH.may_load(storage)?.unwrap_or_default();
+    let mut batch = BATCHES.may_load(storage, id)?.unwrap_or_default();
+    batch.shares = batch.shares.checked_add(shares)?;
+    BATCHES.save(storage, id, &batch)?;
+    CLAIMS.update(storage, (staker, id), |claim| -> StdResult<_> {
+        Ok(claim.unwrap_or_default().checked_add(shares)?)
+    })?;
+    Ok(id)
+}
+
+/// Undelegates the open batch, if it has anything in it and an epoch has passed since the last one.
+pub fn submit(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    delegator: &Addr,
+    denom: &str,
+    unbonding_period: u64,
+    now: &Timestamp,
+) -> StdResult<Vec<CosmosMsg<KujiraMsg>>> {
+    let id = OPEN_BATCH.may_load(storage)?.unwrap_or_default();
+    let Some(mut batch) = BATCHES.may_load(storage, id)? else {
+        return Ok(vec![]);
+    };
+    let due = match LAST_SUBMITTED.may_load(storage)? {
+        Some(last) => *now >= last.plus_seconds(epoch(unbonding_period)),
+        None => true,
+    };
+    if !due {
+        return Ok(vec![]);
+    }
+
+    let amount = to_amount(storage, querier, delegator, denom, batch.shares)?;
+    let (msgs, undelegated) = undelegate(querier, delegator, denom, amount)?;
+    let total = UNDELEGATED
+        .may_load(storage)?
+        .unwrap_or_default()
+        .checked_add(undelegated)?;
+    UNDELEGATED.save(storage, &total)?;
+    batch.amount = undelegated;
+    batch.undelegated_through = total;
+    batch.release_at = Some(now.plus_seconds(unbonding_period));
+    BATCHES.save(storage, id, &batch)?;
+    OPEN_BATCH.save(storage, &(id + 1))?;
+    LAST_SUBMITTED.save(storage, now)?;
+    Ok(msgs)
+}
+
+/// Undelegates `amount`, drawing from the largest delegations first. Returns the amount actually
+/// undelegated, which can fall short of `amount` by rounding.
+fn undelegate(
+    querier: &QuerierWrapper,
+    delegator: &Addr,
+    denom: &str,
+    amount: Uint128,
+) -> StdResult<(Vec<CosmosMsg<KujiraMsg>>, Uint128)> {
+    let mut delegations = delegations(querier, delegator, denom)?;
+    delegations.sort_by_key(|(_, amount)| std::cmp::Reverse(*amount));
+
+    let mut remaining = amount;
+    let mut msgs = vec![];
+    for (validator, delegated) in delegations {
+        if remaining.is_zero() {
+            break;
+        }
+        let undelegated = delegated.min(remaining);
+        remaining -= undelegated;
+        msgs.push(
+            StakingMsg::Undelegate {
+                validator,
+                amount: coin(undelegated.u128(), denom),
+            }
+            .into(),
+        );
+    }
+    Ok((msgs, amount - remaining))
+}
+
+/// Redelegates from validators above their target share of the total delegated, to those below it.
+/// Validators no longer in `validators` have a target of zero.
+pub fn rebalance(
+    querier: &QuerierWrapper,
+    delegator: &Addr,
+    denom: &str,
+    validators: &[(String, Decimal)],
+) -> StdResult<Vec<CosmosMsg<KujiraMsg>>> {
+    let current = delegations(querier, delegator, denom)?;
+    let total: Uint128 = current.iter().map(|(_, amount)| *amount).sum();
+    let targets = split(validators, total);
+
+    let delegated = |validator: &str| {
+        current
+            .iter()
+            .find(|(v, _)| v == validator)
+            .map(|(_, amount)| *amount)
+            .unwrap_or_default()
+    };
+    let target = |validator: &str| {
+        targets
+            .iter()
+            .find(|(v, _)| v == validator)
+            .map(|(_, amount)| *amount)
+            .unwrap_or_default()
+    };
+
+    let mut surpluses: Vec<_> = current
+        .iter()
+        .map(|(v, amount)| (v.clone(), amount.saturating_sub(target(v))))
+        .filter(|(_, surplus)| !surplus.is_zero())
+        .collect();
+    let deficits = targets
+        .iter()
+        .map(|(v, amount)| (v.clone(), amount.saturating_sub(delegated(v))))
+        .filter(|(_, deficit)| !deficit.is_zero());
+
+    let mut msgs = vec![];
+    for (dst_validator, mut deficit) in deficits {
+        for (src_validator, surplus) in surpluses.iter_mut() {
+            if deficit.is_zero() {
+                break;
+            }
+            let moved = deficit.min(*surplus);
+            if moved.is_zero() {
+                continue;
+            }
+            *surplus -= moved;
+            deficit -= moved;
+            msgs.push(
+                StakingMsg::Redelegate {
+                    src_validator: src_validator.clone(),
+                    dst_validator: dst_validator.clone(),
+                    amount: coin(moved.u128(), denom),
+                }
+                .into(),
+            );
+        }
+    }
+    Ok(msgs)
+}
+
+/// Removes `staker`'s claim on batch `id`, returning the tokens it pays out. The batch must have
+/// finished unbonding, with `balance` covering every batch up to it.
+pub fn withdraw(
+    storage: &mut dyn Storage,
+    staker: &Addr,
+    id: u64,
+    balance: Uint128,
+    now: &Timestamp,
+) -> Result<Uint128, ContractError> {
+    let claim = CLAIMS
+        .may_load(storage, (staker, id))?
+        .ok_or(ContractError::NothingToWithdraw {})?;
+    let batch = BATCHES.load(storage, id)?;
+    let withdrawn = WITHDRAWN.may_load(storage)?.unwrap_or_default();
+    let released = match batch.release_at {
+        Some(release_at) => {
+            release_at <= *now && balance >= batch.undelegated_through.saturating_sub(withdrawn)
+        }
+        None => false,
+    };
+    ensure!(released, ContractError::BatchNotReleased(id));
+
+    let amount = claim.multiply_ratio(batch.amount, batch.shares);
+    CLAIMS.remove(storage, (staker, id));
+    WITHDRAWN.save(storage, &withdrawn.checked_add(amount)?)?;
+    Ok(amount)
+}
+
+/// Whether any staker has unstaked tokens left to withdraw.
+pub fn any_unbonding(storage: &dyn Storage) -> StdResult<bool> {
+    Ok(CLAIMS
+        .keys_raw(storage, None, None, Order::Ascending)
+        .next()
+        .is_some())
+}
+
+/// `staker`'s claims on each batch. Claims on the open batch are valued at the current exchange rate.
+pub fn unbonding(
+    storage: &dyn Storage,
+    querier: &QuerierWrapper,
+    delegator: &Addr,
+    denom: &str,
+    staker: &Addr,
+) -> StdResult<Vec<Unbonding>> {
+    CLAIMS
+        .prefix(staker)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (id, claim) = item?;
+            let batch = BATCHES.load(storage, id)?;
+            let amount = match batch.release_at {
+                Some(_) => claim.multiply_ratio(batch.amount, batch.shares),
+                None => to_amount(storage, querier, delegator, denom, claim)?,
+            };
+            Ok(Unbonding {
+                batch: id,
+                amount,
+                release_at: batch.release_at,
+            })
+        })
+        .collect()
+}
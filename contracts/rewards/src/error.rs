@@ -1,4 +1,4 @@
-use cosmwasm_std::{ConversionOverflowError, OverflowError, StdError};
+use cosmwasm_std::{ConversionOverflowError, OverflowError, StdError, Uint128};
 use cw_utils::PaymentError;
 use cw_rewards_logic::RewardsError;
 use thiserror::Error;
@@ -25,6 +25,18 @@ pub enum ContractError {
     #[error("unauthorized")]
     Unauthorized {},
 
+    #[error("No ownership transfer is pending")]
+    NoPendingOwnership {},
+
+    #[error("Only the pending owner can accept ownership")]
+    NotPendingOwner {},
+
+    #[error("The ownership transfer has expired")]
+    OwnershipTransferExpired {},
+
+    #[error("The ownership transfer expiry has already passed")]
+    InvalidOwnershipExpiry {},
+
     #[error("Reward denom not on whitelist")]
     RewardNotWhitelisted {},
 
@@ -45,4 +57,88 @@ pub enum ContractError {
 
     #[error("Inflation not enabled")]
     InflationNotEnabled {},
+
+    #[error("No fees to claim")]
+    NoFeesToClaim {},
+
+    #[error("No swap route for {0}")]
+    NoSwapRoute(String),
+
+    #[error("Invalid swap route for {0}: ask denom must be a target denom, with a max spread of at most one")]
+    InvalidSwapRoute(String),
+
+    #[error("Streams need a non-zero duration")]
+    InvalidStream {},
+
+    #[error("Fee rates must sum to at most one")]
+    InvalidFees {},
+
+    #[error("Inflation top-up shares can't exceed one")]
+    InvalidInflationTopUp {},
+
+    #[error("Stake can only be forwarded to an underlying cw-rewards source")]
+    InvalidStakeForwarding {},
+
+    #[error("Validator weights must be unique and sum to one")]
+    InvalidValidatorWeights {},
+
+    #[error("Nothing to withdraw")]
+    NothingToWithdraw {},
+
+    #[error("Unbonding batch {0} hasn't been released yet")]
+    BatchNotReleased(u64),
+
+    #[error("No unbonding batch is due")]
+    NothingToSubmit {},
+
+    #[error("Stake denoms must be unique, with non-zero multipliers")]
+    InvalidStakeDenoms {},
+
+    #[error("Staker has several denoms staked, so the denom to unstake must be specified")]
+    UnstakeDenomRequired {},
+
+    #[error("The stake receipt can't be changed once set")]
+    ReceiptLocked {},
+
+    #[error("Staking can't be switched from {0} to {1} while tokens are staked")]
+    UnsafeStakingMigration(&'static str, &'static str),
+
+    #[error("Staking can't be switched with more than {0} stakers")]
+    TooManyStakers(usize),
+
+    #[error("{0} can't be removed from staking while it's staked")]
+    DenomStaked(String),
+
+    #[error("Invalid weight update for {0}: {1}")]
+    InvalidWeightUpdate(String, &'static str),
+
+    #[error("Stake exceeds the per-staker cap of {0}")]
+    StakerCapExceeded(Uint128),
+
+    #[error("Stake exceeds the total cap of {0}")]
+    TotalCapExceeded(Uint128),
+
+    #[error("Stake must be at least {0}")]
+    BelowMinStake(Uint128),
+
+    #[error("Stake caps can't be below the minimum stake")]
+    InvalidStakeLimits {},
+
+    #[error("A per-staker stake cap can't be set while stake receipts are minted")]
+    StakerCapWithReceipts {},
+
+    #[error("Slash ratio must be between zero and one, and slash part of the stake")]
+    InvalidSlashRatio {},
+
+    #[error("Stake forwarding can't be changed while tokens are staked")]
+    ForwardStakeLocked {},
+
+    #[error("Stake forwarded to an underlying source can't be slashed")]
+    SlashForwardedStake {},
+
+    #[error("{0} is paused")]
+    Paused(&'static str),
+
+    #[error("Unknown reply id {0}")]
+    UnknownReply(u64),
 }
@@ -1,19 +1,50 @@
-use cosmwasm_std::{coin, Coin, DepsMut, MessageInfo, Response};
-use cw_rewards_logic::util::{calculate_fee_msgs, calculate_fee_split};
+use cosmwasm_std::{
+    coin, coins, ensure, wasm_execute, Addr, BankMsg, Coin, CosmosMsg, Decimal, DepsMut, Empty,
+    Env, Event, MessageInfo, Response, StdResult, Storage, SubMsg, Uint128,
+};
+use cw_rewards_logic::util::{calculate_denom_fee_split, calculate_fee_msgs, calculate_share};
+use cw_rewards_logic::{fee, incentive, inflation, RewardsError};
 use cw_rewards_logic::{ClaimRewardsMsg, DistributeRewardsMsg, StakeMsg, UnstakeMsg};
-use cw_utils::must_pay;
+use cw_utils::{may_pay, must_pay, NativeBalance, PaymentError};
+use kujira::{CallbackData, KujiraMsg};
 
-use crate::msg::{StakingConfig, Whitelist};
-use crate::{contract::STATE_MACHINE, Config, ContractError};
+use crate::msg::{ExecuteMsg, StakingConfig, UnderlyingConfig, Whitelist};
+use crate::{
+    contract::STATE_MACHINE, conversion, delegation, limits, principal, receipt, slash, Config,
+    ContractError,
+};
 
 pub fn stake(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     config: Config,
     msg: StakeMsg,
-) -> Result<Response, ContractError> {
-    let stake_denom = match config.staking_module {
-        StakingConfig::NativeToken { denom } => denom,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let (stake_denom, receipt) = match config.staking_module {
+        StakingConfig::NativeToken { denom, receipt } => (denom, receipt),
+        StakingConfig::NativeTokens { denoms } => return stake_multi(deps, info, &denoms, msg),
+        StakingConfig::Delegated {
+            denom, validators, ..
+        } => {
+            let received = must_pay(&info, &denom)?;
+            let shares = delegation::to_shares(
+                deps.storage,
+                &deps.querier,
+                &env.contract.address,
+                &denom,
+                received,
+            )?;
+            let res = cw_rewards_logic::execute::stake(
+                STATE_MACHINE,
+                deps.storage,
+                coin(shares.u128(), &denom),
+                &info.sender,
+                msg,
+                "rewards/simple",
+            )?;
+            return Ok(res.add_messages(delegation::delegate(&validators, &denom, received)));
+        }
         _ => {
             return Err(ContractError::InvalidStakingConfig(
                 "NativeToken",
@@ -21,27 +52,74 @@ pub fn stake(
             ))
         }
     };
-    let received = must_pay(&info, &stake_denom)?;
+    let mut received = must_pay(&info, &stake_denom)?;
+
+    // Stake over the caps is rejected, or refunded if the limits allow
+    let mut refund = Uint128::zero();
+    if let Some(limits) = &config.stake_limits {
+        (received, refund) = limits::accept(deps.storage, limits, &info.sender, received)?;
+    }
 
-    cw_rewards_logic::execute::stake(
+    // Once stake has been slashed, weights are shares of the remaining stake
+    let shares = slash::to_shares(deps.storage, received)?;
+    let mut res = cw_rewards_logic::execute::stake(
         STATE_MACHINE,
         deps.storage,
-        coin(received.u128(), &stake_denom),
+        coin(shares.u128(), &stake_denom),
         &info.sender,
         msg,
         "rewards/simple",
-    )
-    .map_err(ContractError::from)
+    )?;
+    slash::deposit(deps.storage, received)?;
+
+    if let Some(limits) = &config.stake_limits {
+        limits::check_min(limits, staked(deps.storage, &info.sender)?)?;
+    }
+    if !refund.is_zero() {
+        res = res
+            .add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: coins(refund.u128(), &stake_denom),
+            })
+            .add_attribute("refund", refund.to_string());
+    }
+
+    // Receipts for the new stake are held by the contract until they're withdrawn
+    if let Some(receipt) = &receipt {
+        res = res.add_message(receipt::mint(&env, receipt, shares, &env.contract.address));
+    }
+
+    match forward_stake_to(&config.underlying_rewards_module) {
+        Some(underlying) => {
+            let forward = StakeMsg {
+                withdraw_rewards: false,
+                callback: None,
+            };
+            Ok(res.add_message(wasm_execute(
+                underlying,
+                &ExecuteMsg::Rewards(forward.into()),
+                coins(received.u128(), &stake_denom),
+            )?))
+        }
+        None => Ok(res),
+    }
 }
 
 pub fn unstake(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     config: Config,
     msg: UnstakeMsg,
-) -> Result<Response, ContractError> {
-    let stake_denom = match config.staking_module {
-        StakingConfig::NativeToken { denom } => denom,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let (stake_denom, receipt) = match config.staking_module {
+        StakingConfig::NativeToken { denom, receipt } => (denom, receipt),
+        StakingConfig::NativeTokens { denoms } => return unstake_multi(deps, info, &denoms, msg),
+        StakingConfig::Delegated {
+            denom,
+            unbonding_period,
+            ..
+        } => return unstake_delegated(deps, env, info, &denom, unbonding_period, msg),
         _ => {
             return Err(ContractError::InvalidStakingConfig(
                 "NativeToken",
@@ -50,22 +128,457 @@ pub fn unstake(
         }
     };
 
-    cw_rewards_logic::execute::unstake(
-        STATE_MACHINE,
+    // Receipts sent along are deposited first, so their holder can unstake them
+    if let Some(receipt) = &receipt {
+        let deposited = may_pay(&info, &receipt::denom(&env, receipt))?;
+        if !deposited.is_zero() {
+            STATE_MACHINE.increase_weight(
+                deps.storage,
+                &info.sender.to_string(),
+                deposited,
+                false,
+            )?;
+            receipt::redeem(deps.storage, deposited)?;
+        }
+    }
+
+    let amount = msg.amount;
+    let returned = slash::to_amount(deps.storage, amount)?;
+    let mut res = match returned == amount {
+        true => cw_rewards_logic::execute::unstake(
+            STATE_MACHINE,
+            deps.storage,
+            &info.sender,
+            &stake_denom,
+            msg,
+            "rewards/simple",
+        )?,
+        false => unstake_shares(deps.storage, &info.sender, &stake_denom, returned, msg)?,
+    };
+    slash::withdraw(deps.storage, returned)?;
+
+    if let Some(limits) = &config.stake_limits {
+        limits::check_min(limits, staked(deps.storage, &info.sender)?)?;
+    }
+
+    // The forwarded stake has to return from the underlying contract before it's sent back
+    if let Some(underlying) = forward_stake_to(&config.underlying_rewards_module) {
+        let forward = UnstakeMsg {
+            amount,
+            withdraw_rewards: false,
+            callback: None,
+            denom: None,
+        };
+        let unstake = wasm_execute(underlying, &ExecuteMsg::Rewards(forward.into()), vec![])?;
+        res.messages.insert(0, SubMsg::new(unstake));
+    }
+
+    if let Some(receipt) = &receipt {
+        res = res.add_message(receipt::burn(&env, receipt, amount));
+    }
+
+    Ok(res)
+}
+
+/// Unstakes all of the sender's NativeToken stake, without settling their rewards.
+///
+/// Unsettled rewards are forfeited, and either accrued to the configured forfeit recipient, or
+/// redistributed to the remaining stakers. Rewards already accrued to the staker are kept.
+pub fn emergency_unstake(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    config: Config,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let StakingConfig::NativeToken { denom, receipt } = &config.staking_module else {
+        return Err(ContractError::InvalidStakingConfig(
+            "EmergencyUnstake",
+            config.staking_module,
+        ));
+    };
+
+    let staker = info.sender.to_string();
+    let shares = STATE_MACHINE
+        .user_weights
+        .may_load(deps.storage, &staker)?
+        .unwrap_or_default();
+    ensure!(!shares.is_zero(), RewardsError::ZeroUnstake {});
+    let amount = slash::to_amount(deps.storage, shares)?;
+    let (_, mut forfeited) = STATE_MACHINE.forfeit_weight(deps.storage, &staker)?;
+    slash::withdraw(deps.storage, amount)?;
+    let forfeited_attr = NativeBalance(forfeited.clone()).to_string();
+    match &config.forfeit_recipient {
+        Some(recipient) => fee::accrue(
+            deps.storage,
+            &mut forfeited,
+            &[(Decimal::one(), recipient.clone())],
+        )?,
+        None if !STATE_MACHINE.total_staked.load(deps.storage)?.is_zero() => {
+            STATE_MACHINE.distribute_rewards(deps.storage, &forfeited)?
+        }
+        // Nobody is left to receive them, so they stay in the contract.
+        None => {}
+    }
+
+    let mut res = Response::default();
+    if let Some(underlying) = forward_stake_to(&config.underlying_rewards_module) {
+        let forward = UnstakeMsg {
+            amount,
+            withdraw_rewards: false,
+            callback: None,
+            denom: None,
+        };
+        res = res.add_message(wasm_execute(
+            underlying,
+            &ExecuteMsg::Rewards(forward.into()),
+            vec![],
+        )?);
+    }
+    if let Some(receipt) = receipt {
+        res = res.add_message(receipt::burn(&env, receipt, shares));
+    }
+
+    let event = Event::new("rewards/emergency-unstake").add_attributes(vec![
+        ("staker", staker.clone()),
+        ("weight", shares.to_string()),
+        ("amount", amount.to_string()),
+        ("forfeited", forfeited_attr),
+    ]);
+    Ok(res
+        .add_message(BankMsg::Send {
+            to_address: staker,
+            amount: coins(amount.u128(), denom),
+        })
+        .add_event(event))
+}
+
+pub fn withdraw_receipts(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    config: Config,
+    amount: Uint128,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let Some(receipt) = receipt::subdenom(&config.staking_module) else {
+        return Err(ContractError::InvalidStakingConfig(
+            "WithdrawReceipts",
+            config.staking_module,
+        ));
+    };
+    ensure!(!amount.is_zero(), RewardsError::ZeroUnstake {});
+
+    STATE_MACHINE.decrease_weight(deps.storage, &info.sender.to_string(), amount, false)?;
+    receipt::release(deps.storage, amount)?;
+
+    let event = Event::new("rewards/withdraw-receipts").add_attributes(vec![
+        ("staker", info.sender.to_string()),
+        ("amount", amount.to_string()),
+    ]);
+    Ok(Response::default()
+        .add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(amount.u128(), receipt::denom(&env, receipt)),
+        })
+        .add_event(event))
+}
+
+pub fn deposit_receipts(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    config: Config,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let Some(receipt) = receipt::subdenom(&config.staking_module) else {
+        return Err(ContractError::InvalidStakingConfig(
+            "DepositReceipts",
+            config.staking_module,
+        ));
+    };
+    let amount = must_pay(&info, &receipt::denom(&env, receipt))?;
+
+    STATE_MACHINE.increase_weight(deps.storage, &info.sender.to_string(), amount, false)?;
+    receipt::redeem(deps.storage, amount)?;
+
+    let event = Event::new("rewards/deposit-receipts").add_attributes(vec![
+        ("staker", info.sender.to_string()),
+        ("amount", amount.to_string()),
+    ]);
+    Ok(Response::default().add_event(event))
+}
+
+/// Adds the sent funds to the staker's principal, and sets their weight from it.
+fn stake_multi(
+    deps: DepsMut,
+    info: MessageInfo,
+    denoms: &[(String, Decimal)],
+    msg: StakeMsg,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    ensure!(!info.funds.is_empty(), PaymentError::NoFunds {});
+    for stake in info.funds.iter() {
+        ensure!(
+            denoms.iter().any(|(denom, _)| denom == &stake.denom),
+            PaymentError::ExtraDenom(stake.denom.clone())
+        );
+        principal::add(deps.storage, &info.sender, stake)?;
+    }
+
+    let weight = principal::weight(deps.storage, &info.sender, denoms)?;
+    let accrued = STATE_MACHINE.set_weight(
         deps.storage,
-        &info.sender,
-        &stake_denom,
-        msg,
-        "rewards/simple",
-    )
-    .map_err(ContractError::from)
+        &info.sender.to_string(),
+        weight,
+        msg.withdraw_rewards,
+    )?;
+    let rewards = if msg.withdraw_rewards {
+        accrued
+    } else {
+        vec![]
+    };
+
+    let event = Event::new("rewards/simple/rewards/stake").add_attributes(vec![
+        ("action", "rewards/stake"),
+        ("staker", info.sender.as_str()),
+        ("amount", &NativeBalance(info.funds.clone()).to_string()),
+        ("weight", &weight.to_string()),
+        ("withdraw_rewards", &msg.withdraw_rewards.to_string()),
+    ]);
+    Ok(Response::default()
+        .add_messages(send_rewards(&info.sender, msg.callback, rewards)?)
+        .add_event(event))
+}
+
+/// Returns what `msg.amount` of slashed stake weight is still worth, `returned`.
+fn unstake_shares(
+    storage: &mut dyn Storage,
+    staker: &Addr,
+    denom: &str,
+    returned: Uint128,
+    msg: UnstakeMsg,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    ensure!(!msg.amount.is_zero(), RewardsError::ZeroUnstake {});
+
+    let accrued = STATE_MACHINE.decrease_weight(
+        storage,
+        &staker.to_string(),
+        msg.amount,
+        msg.withdraw_rewards,
+    )?;
+    let mut funds = coins(returned.u128(), denom);
+    if msg.withdraw_rewards {
+        funds = (NativeBalance(funds) + NativeBalance(accrued)).into_vec();
+    }
+
+    let event = Event::new("rewards/simple/rewards/unstake").add_attributes(vec![
+        ("action", "rewards/unstake"),
+        ("staker", staker.as_str()),
+        ("amount", &msg.amount.to_string()),
+        ("returned", &returned.to_string()),
+        ("denom", denom),
+        ("withdraw_rewards", &msg.withdraw_rewards.to_string()),
+    ]);
+    Ok(Response::default()
+        .add_messages(send_rewards(staker, msg.callback, funds)?)
+        .add_event(event))
+}
+
+/// Returns principal in the unstaked denom, and sets the staker's weight from what's left.
+fn unstake_multi(
+    deps: DepsMut,
+    info: MessageInfo,
+    denoms: &[(String, Decimal)],
+    msg: UnstakeMsg,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    ensure!(!msg.amount.is_zero(), RewardsError::ZeroUnstake {});
+
+    let denom = match msg.denom {
+        Some(denom) => denom,
+        None => match principal::all(deps.storage, &info.sender)?.as_slice() {
+            [principal] => principal.denom.clone(),
+            _ => return Err(ContractError::UnstakeDenomRequired {}),
+        },
+    };
+    let unstake = coin(msg.amount.u128(), &denom);
+    principal::sub(deps.storage, &info.sender, &unstake)?;
+
+    let weight = principal::weight(deps.storage, &info.sender, denoms)?;
+    let accrued = STATE_MACHINE.set_weight(
+        deps.storage,
+        &info.sender.to_string(),
+        weight,
+        msg.withdraw_rewards,
+    )?;
+    let mut returned = vec![unstake];
+    if msg.withdraw_rewards {
+        returned = (NativeBalance(returned) + NativeBalance(accrued)).into_vec();
+    }
+
+    let event = Event::new("rewards/simple/rewards/unstake").add_attributes(vec![
+        ("action", "rewards/unstake"),
+        ("staker", info.sender.as_str()),
+        ("amount", &msg.amount.to_string()),
+        ("denom", &denom),
+        ("weight", &weight.to_string()),
+        ("withdraw_rewards", &msg.withdraw_rewards.to_string()),
+    ]);
+    Ok(Response::default()
+        .add_messages(send_rewards(&info.sender, msg.callback, returned)?)
+        .add_event(event))
+}
+
+/// The tokens `staker` has staked under NativeToken staking.
+fn staked(storage: &dyn Storage, staker: &Addr) -> StdResult<Uint128> {
+    let weight = STATE_MACHINE
+        .user_weights
+        .may_load(storage, &staker.to_string())?
+        .unwrap_or_default();
+    slash::to_amount(storage, weight)
+}
+
+/// Sends `funds` to `user`, through their callback if they set one.
+fn send_rewards(
+    user: &Addr,
+    callback: Option<CallbackData>,
+    funds: Vec<Coin>,
+) -> StdResult<Option<CosmosMsg<KujiraMsg>>> {
+    match (callback, funds.is_empty()) {
+        (Some(cb), _) => Ok(Some(cb.to_message(user, Empty {}, funds)?)),
+        (None, false) => Ok(Some(
+            BankMsg::Send {
+                to_address: user.to_string(),
+                amount: funds,
+            }
+            .into(),
+        )),
+        (None, true) => Ok(None),
+    }
+}
+
+/// Queues the unstaked shares in the open batch, undelegating it if it's due. The batch's tokens can be
+/// withdrawn with [`ExecuteMsg::WithdrawUnbonded`] once they've finished unbonding.
+fn unstake_delegated(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    denom: &str,
+    unbonding_period: u64,
+    msg: UnstakeMsg,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    ensure!(!msg.amount.is_zero(), RewardsError::ZeroUnstake {});
+
+    let accrued = STATE_MACHINE.decrease_weight(
+        deps.storage,
+        &info.sender.to_string(),
+        msg.amount,
+        msg.withdraw_rewards,
+    )?;
+    let rewards = if msg.withdraw_rewards {
+        accrued
+    } else {
+        vec![]
+    };
+    let batch = delegation::queue(deps.storage, &info.sender, msg.amount)?;
+    let undelegate_msgs = delegation::submit(
+        deps.storage,
+        &deps.querier,
+        &env.contract.address,
+        denom,
+        unbonding_period,
+        &env.block.time,
+    )?;
+
+    let event = Event::new("rewards/simple/rewards/unstake").add_attributes(vec![
+        ("action", "rewards/unstake"),
+        ("staker", info.sender.as_str()),
+        ("amount", &msg.amount.to_string()),
+        ("denom", denom),
+        ("withdraw_rewards", &msg.withdraw_rewards.to_string()),
+        ("batch", &batch.to_string()),
+    ]);
+    let res = Response::default()
+        .add_messages(undelegate_msgs)
+        .add_event(event);
+
+    Ok(res.add_messages(send_rewards(&info.sender, msg.callback, rewards)?))
+}
+
+pub fn withdraw_unbonded(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    config: Config,
+    batch: u64,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let denom = match config.staking_module {
+        StakingConfig::Delegated { denom, .. } => denom,
+        _ => {
+            return Err(ContractError::InvalidStakingConfig(
+                "WithdrawUnbonded",
+                config.staking_module,
+            ))
+        }
+    };
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &denom)?
+        .amount;
+    let amount = delegation::withdraw(deps.storage, &info.sender, batch, balance, &env.block.time)?;
+
+    let event = Event::new("rewards/withdraw-unbonded").add_attributes(vec![
+        ("staker", info.sender.to_string()),
+        ("batch", batch.to_string()),
+        ("amount", amount.to_string()),
+    ]);
+    let mut res = Response::default().add_event(event);
+    if !amount.is_zero() {
+        res = res.add_message(BankMsg::Send {
+            to_address: info.sender.to_string(),
+            amount: coins(amount.u128(), denom),
+        });
+    }
+    Ok(res)
+}
+
+pub fn submit_unbonding(
+    deps: DepsMut,
+    env: Env,
+    config: Config,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let StakingConfig::Delegated {
+        denom,
+        unbonding_period,
+        ..
+    } = &config.staking_module
+    else {
+        return Err(ContractError::InvalidStakingConfig(
+            "SubmitUnbonding",
+            config.staking_module,
+        ));
+    };
+    let msgs = delegation::submit(
+        deps.storage,
+        &deps.querier,
+        &env.contract.address,
+        denom,
+        *unbonding_period,
+        &env.block.time,
+    )?;
+    ensure!(!msgs.is_empty(), ContractError::NothingToSubmit {});
+
+    let event = Event::new("rewards/submit-unbonding");
+    Ok(Response::default().add_messages(msgs).add_event(event))
+}
+
+fn forward_stake_to(underlying_cfg: &Option<UnderlyingConfig>) -> Option<&Addr> {
+    underlying_cfg
+        .as_ref()
+        .and_then(|cfg| cfg.forward_stake_to.as_ref())
 }
 
 pub fn claim(
     deps: DepsMut,
     info: MessageInfo,
     msg: ClaimRewardsMsg,
-) -> Result<Response, ContractError> {
+) -> Result<Response<KujiraMsg>, ContractError> {
     cw_rewards_logic::execute::claim(
         STATE_MACHINE,
         deps.storage,
@@ -77,11 +590,12 @@ pub fn claim(
 }
 
 pub fn distribute(
-    deps: DepsMut,
+    mut deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     config: Config,
     msg: DistributeRewardsMsg,
-) -> Result<Response, ContractError> {
+) -> Result<Response<KujiraMsg>, ContractError> {
     if info.funds.is_empty() {
         return Err(ContractError::ZeroRewards {});
     }
@@ -101,8 +615,39 @@ pub fn distribute(
 
     // Fee split
     let mut rewards = info.funds;
-    let fees = calculate_fee_split(&mut rewards, &distribution_cfg.fees);
-    let msgs = calculate_fee_msgs(fees);
+    let fees = calculate_denom_fee_split(
+        &mut rewards,
+        &distribution_cfg.fees,
+        &distribution_cfg.denom_fees,
+    );
+    let msgs = calculate_fee_msgs(fees)?;
+
+    // Inflation top-up, only routed while the inflation module is enabled, and if the pool can hold it
+    if let (Some(top_up), Some(_)) = (&distribution_cfg.inflation_top_up, &config.inflation_module)
+    {
+        if inflation::accepts(deps.storage, &top_up.denom)? {
+            if let Some(funds) =
+                calculate_share(&mut rewards, &top_up.denom, top_up.distribution_share)
+            {
+                inflation::fund(deps.storage, funds)?;
+            }
+        }
+    }
+
+    // Non-target denoms are swapped, and their proceeds distributed in the reply
+    let swaps = match &distribution_cfg.conversion {
+        Some(conversion) => conversion::convert(&mut deps, &env, conversion, &mut rewards)?,
+        None => vec![],
+    };
+
+    // Streamed rewards are released by the incentive crank instead
+    let rewards = match &distribution_cfg.stream {
+        Some(stream) => {
+            incentive::stream(deps.storage, &rewards, &env.block.time, stream.duration)?;
+            vec![]
+        }
+        None => rewards,
+    };
 
     cw_rewards_logic::execute::distribute_rewards(
         STATE_MACHINE,
@@ -112,5 +657,8 @@ pub fn distribute(
         msg,
         "rewards/simple",
     )
-    .map_or_else(|e| Err(e.into()), |res| Ok(res.add_messages(msgs)))
+    .map_or_else(
+        |e| Err(e.into()),
+        |res| Ok(res.add_submessages(swaps).add_messages(msgs)),
+    )
 }
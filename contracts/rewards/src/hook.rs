@@ -0,0 +1,158 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Order, QuerierWrapper, StdResult, Storage, Uint128};
+use cw_storage_plus::{Bound, Item};
+
+use crate::{
+    contract::STATE_MACHINE,
+    msg::{ResyncStatus, StakingConfig},
+};
+
+/// Page size for listing a hook source's stakers, the maximum both DAO DAO and cw4 allow.
+const PAGE_LIMIT: u32 = 30;
+
+#[cw_serde]
+enum DaoDaoQueryMsg {
+    ListStakers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[cw_serde]
+struct ListStakersResponse {
+    stakers: Vec<StakerBalanceResponse>,
+}
+
+#[cw_serde]
+struct StakerBalanceResponse {
+    address: String,
+    balance: Uint128,
+}
+
+/// A page of stakers and their weights from the hook source of `staking`, or nothing if it isn't a hook.
+pub fn list_weights(
+    querier: &QuerierWrapper,
+    staking: &StakingConfig,
+    start_after: Option<String>,
+    limit: u32,
+) -> StdResult<Vec<(String, Uint128)>> {
+    let limit = Some(limit.min(PAGE_LIMIT));
+    match staking {
+        StakingConfig::DaoDaoHook { daodao_addr } => {
+            let res: ListStakersResponse = querier.query_wasm_smart(
+                daodao_addr,
+                &DaoDaoQueryMsg::ListStakers { start_after, limit },
+            )?;
+            Ok(res
+                .stakers
+                .into_iter()
+                .map(|s| (s.address, s.balance))
+                .collect())
+        }
+        StakingConfig::Cw4Hook { cw4_addr } => {
+            let res: cw4::MemberListResponse = querier.query_wasm_smart(
+                cw4_addr,
+                &cw4::Cw4QueryMsg::ListMembers { start_after, limit },
+            )?;
+            Ok(res
+                .members
+                .into_iter()
+                .map(|m| (m.addr, m.weight.into()))
+                .collect())
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+/// Progress of the current, or last, resync.
+const RESYNC: Item<ResyncStatus> = Item::new("hook_resync");
+
+pub fn is_hook(staking: &StakingConfig) -> bool {
+    matches!(
+        staking,
+        StakingConfig::DaoDaoHook { .. } | StakingConfig::Cw4Hook { .. }
+    )
+}
+
+/// Reconciles a page of stakers with the hook source of `staking`, continuing where the last page of a
+/// resync in progress left off, or otherwise starting a new resync after `start_after`. Stakers weighted
+/// here but missing from the source within the page's range are zeroed.
+pub fn resync(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    staking: &StakingConfig,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ResyncStatus> {
+    let mut status = RESYNC.may_load(storage)?.unwrap_or_default();
+    let start_after = match status.in_progress {
+        true => status.cursor.clone(),
+        false => {
+            status = ResyncStatus::default();
+            start_after
+        }
+    };
+    let limit = limit.unwrap_or(PAGE_LIMIT).clamp(1, PAGE_LIMIT);
+
+    let weights = list_weights(querier, staking, start_after.clone(), limit)?;
+    let complete = weights.len() < limit as usize;
+    let end = match complete {
+        true => None,
+        false => weights.last().map(|(staker, _)| staker.clone()),
+    };
+
+    let stale = STATE_MACHINE
+        .user_weights
+        .keys(
+            storage,
+            start_after.as_ref().map(Bound::exclusive),
+            end.as_ref().map(Bound::inclusive),
+            Order::Ascending,
+        )
+        .filter(|staker| {
+            staker
+                .as_ref()
+                .map_or(true, |staker| weights.iter().all(|(s, _)| s != staker))
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    for staker in stale {
+        STATE_MACHINE.set_weight(storage, &staker, Uint128::zero(), false)?;
+    }
+
+    status.synced += weights.len() as u64;
+    for (staker, weight) in weights {
+        STATE_MACHINE.set_weight(storage, &staker, weight, false)?;
+    }
+
+    status.in_progress = !complete;
+    status.cursor = end;
+    RESYNC.save(storage, &status)?;
+    Ok(status)
+}
+
+pub fn resync_status(storage: &dyn Storage) -> StdResult<ResyncStatus> {
+    Ok(RESYNC.may_load(storage)?.unwrap_or_default())
+}
+
+/// Whether `staker`'s weight has been synced with the hook source. Stakers a resync in progress hasn't
+/// reached yet will have their weight set from the source once it does, so changes to it until then are
+/// already accounted for.
+pub fn is_synced(storage: &dyn Storage, staker: &str) -> StdResult<bool> {
+    let status = resync_status(storage)?;
+    Ok(!status.in_progress || matches!(status.cursor.as_deref(), Some(cursor) if staker <= cursor))
+}
+
+/// Starts a fresh resync of the hook source of `staking`, if it's a hook, syncing its first page. Any
+/// further pages are left to [`ExecuteMsg::ResyncWeights`](crate::msg::ExecuteMsg::ResyncWeights).
+/// Fails if the source can't be listed.
+pub fn backfill(
+    storage: &mut dyn Storage,
+    querier: &QuerierWrapper,
+    staking: &StakingConfig,
+) -> StdResult<()> {
+    RESYNC.remove(storage);
+    if is_hook(staking) {
+        resync(storage, querier, staking, None, None)?;
+    }
+    Ok(())
+}
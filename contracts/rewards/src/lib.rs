@@ -1,12 +1,27 @@
 pub mod contract;
 
 mod config;
+mod conversion;
+mod delegation;
 mod error;
 mod execute;
+mod hook;
+mod limits;
+mod managers;
+mod ownership;
+mod pause;
+mod principal;
 mod query;
 mod migration;
+mod receipt;
+mod roles;
+mod slash;
+mod snapshot;
 #[cfg(test)]
 mod testing;
+mod transition;
+mod underlying;
+mod weights;
 
 pub mod msg;
 
@@ -0,0 +1,53 @@
+use cosmwasm_std::{ensure, Addr, Storage, Uint128};
+
+use crate::{contract::STATE_MACHINE, msg::StakeLimits, slash, ContractError};
+
+/// Splits `amount` staked by `staker` into what the stake caps accept, and the excess to refund. Without
+/// `refund_excess`, any stake over a cap is rejected.
+pub fn accept(
+    storage: &dyn Storage,
+    limits: &StakeLimits,
+    staker: &Addr,
+    amount: Uint128,
+) -> Result<(Uint128, Uint128), ContractError> {
+    let staked = STATE_MACHINE
+        .user_weights
+        .may_load(storage, &staker.to_string())?
+        .unwrap_or_default();
+    let staked = slash::to_amount(storage, staked)?;
+    // Includes stake whose receipts have been released
+    let total = slash::pooled(storage)?;
+
+    let mut accepted = amount;
+    let mut exceeded = None;
+    if let Some(cap) = limits.max_per_staker {
+        let room = cap.saturating_sub(staked);
+        if room < accepted {
+            accepted = room;
+            exceeded = Some(ContractError::StakerCapExceeded(cap));
+        }
+    }
+    if let Some(cap) = limits.max_total {
+        let room = cap.saturating_sub(total);
+        if room < accepted {
+            accepted = room;
+            exceeded = Some(ContractError::TotalCapExceeded(cap));
+        }
+    }
+
+    match exceeded {
+        Some(err) if !limits.refund_excess || accepted.is_zero() => Err(err),
+        _ => Ok((accepted, amount - accepted)),
+    }
+}
+
+/// Stakers can't be left with less than `min_stake` tokens staked, unless they've unstaked everything.
+pub fn check_min(limits: &StakeLimits, staked: Uint128) -> Result<(), ContractError> {
+    if let Some(min_stake) = limits.min_stake {
+        ensure!(
+            staked.is_zero() || staked >= min_stake,
+            ContractError::BelowMinStake(min_stake)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Addr, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::{Bound, Map};
+
+use crate::{
+    msg::{WeightManagerResponse, WeightQuota},
+    query::MAX_LIMIT,
+};
+
+#[cw_serde]
+pub struct Manager {
+    pub quota: WeightQuota,
+    /// Total weight granted by the manager, counted against `quota.max_weight`.
+    pub used: Uint128,
+}
+
+const MANAGERS: Map<&Addr, Manager> = Map::new("weight_managers");
+
+/// What removed managers had granted, restored if they're registered again, since their slices stay in
+/// [`GRANTED`].
+const REMOVED: Map<&Addr, Uint128> = Map::new("weight_managers_removed");
+
+/// The slice of each staker's weight granted by each manager.
+const GRANTED: Map<(&Addr, &Addr), Uint128> = Map::new("weight_managers_granted");
+
+/// Registers `manager` with `quota`, or updates the quota of a registered manager, keeping what it has
+/// already granted, including before it was last removed.
+pub fn register(storage: &mut dyn Storage, manager: &Addr, quota: WeightQuota) -> StdResult<()> {
+    let used = match MANAGERS.may_load(storage, manager)? {
+        Some(m) => m.used,
+        None => REMOVED.may_load(storage, manager)?.unwrap_or_default(),
+    };
+    REMOVED.remove(storage, manager);
+    MANAGERS.save(storage, manager, &Manager { quota, used })
+}
+
+/// Revokes `manager`'s rights. The weight it granted stays with the stakers.
+pub fn remove(storage: &mut dyn Storage, manager: &Addr) -> StdResult<()> {
+    if let Some(m) = MANAGERS.may_load(storage, manager)? {
+        MANAGERS.remove(storage, manager);
+        if !m.used.is_zero() {
+            REMOVED.save(storage, manager, &m.used)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn load(storage: &dyn Storage, manager: &Addr) -> StdResult<Option<Manager>> {
+    MANAGERS.may_load(storage, manager)
+}
+
+pub fn save(storage: &mut dyn Storage, manager: &Addr, info: &Manager) -> StdResult<()> {
+    MANAGERS.save(storage, manager, info)
+}
+
+pub fn granted(storage: &dyn Storage, manager: &Addr, staker: &Addr) -> StdResult<Uint128> {
+    Ok(GRANTED
+        .may_load(storage, (manager, staker))?
+        .unwrap_or_default())
+}
+
+pub fn grant(
+    storage: &mut dyn Storage,
+    manager: &Addr,
+    staker: &Addr,
+    weight: Uint128,
+) -> StdResult<()> {
+    if weight.is_zero() {
+        GRANTED.remove(storage, (manager, staker));
+        Ok(())
+    } else {
+        GRANTED.save(storage, (manager, staker), &weight)
+    }
+}
+
+pub fn query_manager(storage: &dyn Storage, manager: Addr) -> StdResult<WeightManagerResponse> {
+    let Manager { quota, used } = MANAGERS.load(storage, &manager)?;
+    Ok(WeightManagerResponse {
+        manager,
+        quota,
+        used,
+    })
+}
+
+pub fn query_managers(
+    storage: &dyn Storage,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+) -> StdResult<Vec<WeightManagerResponse>> {
+    MANAGERS
+        .range(
+            storage,
+            start_after.as_ref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit.unwrap_or(30).min(MAX_LIMIT) as usize)
+        .map(|item| {
+            item.map(|(manager, Manager { quota, used })| WeightManagerResponse {
+                manager,
+                quota,
+                used,
+            })
+        })
+        .collect()
+}
@@ -1,6 +1,8 @@
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{CosmosMsg, DepsMut, Env, Response, StdError};
 use cw2::{get_contract_version, set_contract_version, ContractVersion};
+use cw_rewards_logic::underlying::UnderlyingSource;
+use kujira::KujiraMsg;
 
 use crate::{
     contract::CONTRACT_NAME,
@@ -15,7 +17,7 @@ pub fn do_migrate(
     mut deps: DepsMut,
     _env: Env,
     _msg: MigrateMsg,
-) -> Result<Response, ContractError> {
+) -> Result<Response<KujiraMsg>, ContractError> {
     let ContractVersion {
         contract: name,
         mut version,
@@ -89,11 +91,12 @@ mod v_2_0_0 {
 pub fn migrate_1_x_x_to_2_0_0(
     deps: &mut DepsMut,
     version: &mut String,
-) -> Result<Vec<CosmosMsg>, ContractError> {
+) -> Result<Vec<CosmosMsg<KujiraMsg>>, ContractError> {
     let old_cfg = v_1_x_x::CONFIG.load(deps.storage)?;
     let staking_cfg = match (old_cfg.hook_src, old_cfg.stake_denom) {
         (None, Some(stake_denom)) => StakingConfig::NativeToken {
             denom: stake_denom.to_string(),
+            receipt: None,
         },
         (Some(hook_src), None) => {
             let cw2_info = cw2::query_contract_info(&deps.querier, &hook_src)?;
@@ -115,15 +118,28 @@ pub fn migrate_1_x_x_to_2_0_0(
         min_size: o.incentive_min,
         fee: Some(o.incentive_fee),
         whitelisted_denoms: Whitelist::All,
+        reward_fees: vec![],
     });
     let distribution_cfg = DistributionConfig {
         whitelisted_denoms: old_cfg.whitelisted_rewards,
-        fees: old_cfg.fees,
+        fees: old_cfg
+            .fees
+            .into_iter()
+            .map(|(rate, addr)| (rate, addr.into()))
+            .collect(),
+        denom_fees: vec![],
+        inflation_top_up: None,
+        conversion: None,
+        stream: None,
     };
     let underlying_cfg = old_cfg
         .underlying_rewards
         .map(|underlying| UnderlyingConfig {
-            underlying_rewards_contract: underlying,
+            sources: vec![UnderlyingSource::CwRewards {
+                contract: underlying,
+            }],
+            forward_stake_to: None,
+            reward_fees: vec![],
         });
 
     let new_cfg = v_2_0_0::Config {
@@ -144,16 +160,18 @@ pub fn migrate_1_x_x_to_2_0_0(
 pub fn migrate_2_0_0_to_2_1_0(
     deps: &mut DepsMut,
     version: &mut String,
-) -> Result<Vec<CosmosMsg>, ContractError> {
+) -> Result<Vec<CosmosMsg<KujiraMsg>>, ContractError> {
     let old_cfg = v_2_0_0::CONFIG.load(deps.storage)?;
 
     let new_cfg = crate::config::Config {
-        owner: old_cfg.owner,
+        owner: Some(old_cfg.owner),
         staking_module: old_cfg.staking_module,
         incentive_module: old_cfg.incentive_module,
         distribution_module: old_cfg.distribution_module,
         underlying_rewards_module: old_cfg.underlying_rewards_module,
         inflation_module: None,
+        stake_limits: None,
+        forfeit_recipient: None,
     };
 
     new_cfg.save(deps.storage, deps.api)?;
@@ -1,6 +1,10 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Coin, Decimal, Uint128};
-use cw_rewards_logic::{PendingRewardsResponse, RewardsMsg, StakeInfoResponse};
+use cosmwasm_std::{Addr, Coin, Decimal, Timestamp, Uint128};
+use cw_rewards_logic::{
+    underlying::UnderlyingSource, FeeRecipient, PendingRewardsResponse, RewardsMsg,
+    StakeInfoResponse,
+};
+use cw_utils::Expiration;
 use kujira::{bow::staking::IncentivesResponse, Schedule};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -15,13 +19,31 @@ pub struct InstantiateMsg {
     pub distribution_module: Option<DistributionConfig>,
     pub underlying_rewards_module: Option<UnderlyingConfig>,
     pub inflation_module: Option<InflationConfig>,
+    #[serde(default)]
+    pub stake_limits: Option<StakeLimits>,
+    /// Receives the rewards forfeited by an [`ExecuteMsg::EmergencyUnstake`]. If unset, they are
+    /// redistributed to the remaining stakers.
+    #[serde(default)]
+    pub forfeit_recipient: Option<Addr>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
-#[allow(clippy::derive_partial_eq_without_eq)]
+#[allow(clippy::derive_partial_eq_without_eq, clippy::large_enum_variant)]
 pub enum ExecuteMsg {
     UpdateConfig(ConfigUpdate),
+    /// Proposes, accepts or renounces ownership, in two steps so the owner can't be lost to a typo
+    UpdateOwnership(OwnershipAction),
+    /// Grants `role` to `addr`. Only callable by the owner
+    GrantRole {
+        role: Role,
+        addr: Addr,
+    },
+    /// Revokes `role` from `addr`. Only callable by the owner
+    RevokeRole {
+        role: Role,
+        addr: Addr,
+    },
     /// Adds an incentive with the specified [`Schedule`]. Only works if incentives modules is enabled.
     AddIncentive {
         denom: String,
@@ -38,10 +60,82 @@ pub enum ExecuteMsg {
     StakeChangeHook(StakeChangedHookMsg),
     /// Weight change hook from the CW4 contract
     MemberChangedHook(cw4::MemberChangedHookMsg),
-    /// Manual weight change from the owner. Only works if staking module is set to Permissioned
+    /// Manual weight change from the owner, setting absolute weights like `SetWeights`. Only works if staking
+    /// module is set to Permissioned
     AdjustWeights {
         delta: Vec<(Addr, Uint128)>,
     },
+    /// Sets absolute weights. Only works if staking module is set to Permissioned
+    SetWeights {
+        weights: Vec<(Addr, Uint128)>,
+    },
+    /// Adds to current weights. Only works if staking module is set to Permissioned
+    IncreaseWeights {
+        weights: Vec<(Addr, Uint128)>,
+    },
+    /// Subtracts from current weights. Only works if staking module is set to Permissioned
+    DecreaseWeights {
+        weights: Vec<(Addr, Uint128)>,
+    },
+    /// Lets `manager` set weights within `quota`, or updates its quota. Only callable by a
+    /// [`Role::WeightAdmin`]
+    RegisterWeightManager {
+        manager: Addr,
+        quota: WeightQuota,
+    },
+    /// Revokes `manager`'s rights to set weights. Only callable by a [`Role::WeightAdmin`]
+    RemoveWeightManager {
+        manager: Addr,
+    },
+    /// Slashes `ratio` of all NativeToken stake, sending it to `recipient`. Only callable by the owner or
+    /// the slasher
+    Slash {
+        ratio: Decimal,
+        recipient: Addr,
+    },
+    /// Sets, or with `None` removes, the slasher. Only callable by the owner
+    SetSlasher {
+        slasher: Option<Addr>,
+    },
+    /// Pauses, or unpauses, each operation. Only callable by a [`Role::Pauser`] or the guardian
+    SetPaused(PauseFlags),
+    /// Sets, or with `None` removes, the guardian. Only callable by a [`Role::Pauser`]
+    SetGuardian {
+        guardian: Option<Addr>,
+    },
+    /// Unstakes all of the sender's NativeToken stake, returning it without settling their rewards, so it
+    /// works even while unstaking is paused, or settling rewards fails. Rewards accrued since the sender
+    /// last staked, unstaked or claimed are forfeited
+    EmergencyUnstake {},
+    /// Withdraw the protocol fees accrued to the sender from incentives, inflation and underlying rewards.
+    ClaimFees {},
+    /// Releases `amount` of the sender's stake receipts to them, removing their stake weight. Only works if
+    /// the NativeToken staking module mints receipts
+    WithdrawReceipts {
+        amount: Uint128,
+    },
+    /// Deposits the sent stake receipts, crediting their stake weight to the sender. Only works if the
+    /// NativeToken staking module mints receipts
+    DepositReceipts {},
+    /// Reconciles a page of stake weights with the hook source, continuing from the last page of a resync in
+    /// progress, or otherwise starting one after `start_after`. Only works if staking module is set to a hook
+    ResyncWeights {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Withdraw the sender's unstaked tokens from `batch`, once it has finished unbonding. Only works if
+    /// staking module is set to Delegated
+    WithdrawUnbonded {
+        batch: u64,
+    },
+    /// Undelegates the queued unstakes, if an epoch has passed since the last batch. Only works if staking
+    /// module is set to Delegated
+    SubmitUnbonding {},
+    /// Sets new validator weights, and redelegates the current stake to match them. Only works if staking
+    /// module is set to Delegated
+    RebalanceDelegations {
+        validators: Vec<(String, Decimal)>,
+    },
     /// Rewards interfaces
     #[serde(untagged)]
     Rewards(RewardsMsg),
@@ -52,6 +146,19 @@ pub enum ExecuteMsg {
 pub enum QueryMsg {
     #[returns(Config)]
     Config {},
+    /// The owner, and any pending ownership transfer.
+    #[returns(Ownership)]
+    Ownership {},
+    /// The roles held by `addr`. The owner holds every role.
+    #[returns(RolesResponse)]
+    Roles { addr: Addr },
+    /// The addresses granted `role`, besides the owner.
+    #[returns(Vec<Addr>)]
+    RoleMembers {
+        role: Role,
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    },
     #[returns(PendingRewardsResponse)]
     PendingRewards { staker: Addr },
     #[returns(StakeInfoResponse)]
@@ -68,6 +175,34 @@ pub enum QueryMsg {
     },
     #[returns(InflationResponse)]
     Inflation {},
+    /// Projects inflation emissions and the pool's runway at the current total staked amount.
+    #[returns(InflationProjectionResponse)]
+    InflationProjection {},
+    /// Protocol fees accrued to `recipient`, pending withdrawal.
+    #[returns(AccruedFeesResponse)]
+    AccruedFees { recipient: Addr },
+    #[returns(WeightManagerResponse)]
+    WeightManager { manager: Addr },
+    #[returns(Vec<WeightManagerResponse>)]
+    WeightManagers {
+        start_after: Option<Addr>,
+        limit: Option<u32>,
+    },
+    /// The guardian, and which operations are paused.
+    #[returns(PauseResponse)]
+    Pause {},
+    /// The slasher, and the tokens backing NativeToken stake weights.
+    #[returns(SlashingResponse)]
+    Slashing {},
+    /// Progress of the current, or last, weight resync.
+    #[returns(ResyncStatus)]
+    ResyncStatus {},
+    /// The tokens staked by `staker` under NativeTokens staking, per denom.
+    #[returns(PrincipalResponse)]
+    Principal { staker: Addr },
+    /// Unstaked tokens of `staker` still unbonding, or waiting to be withdrawn.
+    #[returns(UnbondingResponse)]
+    Unbonding { staker: Addr },
 }
 
 #[cw_serde]
@@ -78,10 +213,33 @@ pub enum Whitelist {
 
 #[cw_serde]
 pub enum StakingConfig {
-    NativeToken { denom: String },
-    Cw4Hook { cw4_addr: Addr },
-    DaoDaoHook { daodao_addr: Addr },
+    NativeToken {
+        denom: String,
+        /// Optionally mints a tokenfactory receipt, `factory/{contract}/{receipt}`, 1:1 on stake, and burns
+        /// it on unstake. Can't be changed once set.
+        #[serde(default)]
+        receipt: Option<String>,
+    },
+    /// Users stake any of several native `denoms`, each weighted by its multiplier. Changed multipliers
+    /// apply to a staker's weight the next time they stake or unstake.
+    NativeTokens {
+        denoms: Vec<(String, Decimal)>,
+    },
+    Cw4Hook {
+        cw4_addr: Addr,
+    },
+    DaoDaoHook {
+        daodao_addr: Addr,
+    },
     Permissioned {},
+    /// Staked tokens are delegated across `validators`, split by weight. Weights must sum to one, and
+    /// `unbonding_period` should match the chain's unbonding time, in seconds. Unstakes are undelegated
+    /// in batches, at most one every seventh of it.
+    Delegated {
+        denom: String,
+        validators: Vec<(String, Decimal)>,
+        unbonding_period: u64,
+    },
 }
 
 #[cw_serde]
@@ -90,23 +248,136 @@ pub struct IncentiveConfig {
     pub min_size: Uint128,
     pub fee: Option<Coin>,
     pub whitelisted_denoms: Whitelist,
+    /// Protocol fees taken from incentive rewards as they're released, accrued for [`ExecuteMsg::ClaimFees`].
+    #[serde(default)]
+    pub reward_fees: Vec<(Decimal, Addr)>,
 }
 
 #[cw_serde]
 pub struct DistributionConfig {
-    pub fees: Vec<(Decimal, Addr)>,
+    /// Fee rates and their recipients. A recipient can be an address, or an address with a
+    /// [`FeeAction`](cw_rewards_logic::FeeAction).
+    pub fees: Vec<(Decimal, FeeRecipient)>,
+    /// Per-denom fee schedules, used instead of `fees` for the listed denoms.
+    #[serde(default)]
+    pub denom_fees: Vec<(String, Vec<(Decimal, FeeRecipient)>)>,
+    /// Optionally redirects part of the inflows into the inflation pool.
+    pub inflation_top_up: Option<InflationTopUp>,
+    /// Optionally swaps distributed denoms into a set of target denoms before they're distributed.
+    pub conversion: Option<ConversionConfig>,
+    /// Optionally streams each distribution to stakers over time, rather than distributing it at once.
+    pub stream: Option<StreamConfig>,
     pub whitelisted_denoms: Whitelist,
 }
 
+#[cw_serde]
+pub struct StreamConfig {
+    /// Seconds over which each distribution is released.
+    pub duration: u64,
+    /// Maximum incentives released per crank, when the incentive module is disabled.
+    pub crank_limit: usize,
+}
+
+#[cw_serde]
+pub struct ConversionConfig {
+    /// Denoms distributed as-is. Every other denom must have a route in `routes`.
+    pub target_denoms: Vec<String>,
+    pub routes: Vec<SwapRoute>,
+}
+
+#[cw_serde]
+pub struct SwapRoute {
+    pub offer_denom: String,
+    /// Must be one of the target denoms.
+    pub ask_denom: String,
+    /// A contract implementing the Kujira FIN `Swap` interface, such as a FIN pair.
+    pub contract: Addr,
+    /// Passed to the swap, which fails rather than fill beyond this spread.
+    pub max_spread: Decimal,
+}
+
+#[cw_serde]
+pub struct InflationTopUp {
+    /// Only this denom is routed into the inflation pool, so it must match the pool's denom.
+    pub denom: String,
+    /// Portion of each direct distribution, after fees, redirected into the inflation pool.
+    pub distribution_share: Decimal,
+    /// Portion of incentive creation fees redirected into the inflation pool.
+    pub incentive_fee_share: Decimal,
+}
+
 #[cw_serde]
 pub struct UnderlyingConfig {
-    pub underlying_rewards_contract: Addr,
+    /// The sources of rewards this contract earns upstream. Also accepts cw-rewards contract addresses,
+    /// alone or in a list, as stored under `underlying_rewards_contract(s)` before other sources were
+    /// supported.
+    #[serde(
+        alias = "underlying_rewards_contract",
+        alias = "underlying_rewards_contracts",
+        deserialize_with = "legacy_sources"
+    )]
+    pub sources: Vec<UnderlyingSource>,
+    /// Optionally forwards NativeToken stake into one of the cw-rewards `sources`, which must stake the
+    /// same denom, and unstakes from it on unstake.
+    #[serde(default)]
+    pub forward_stake_to: Option<Addr>,
+    /// Protocol fees taken from underlying rewards as they're claimed, accrued for [`ExecuteMsg::ClaimFees`].
+    #[serde(default)]
+    pub reward_fees: Vec<(Decimal, Addr)>,
+}
+
+fn legacy_sources<'de, D>(deserializer: D) -> Result<Vec<UnderlyingSource>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Source {
+        CwRewards(Addr),
+        Source(UnderlyingSource),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Source),
+        Many(Vec<Source>),
+    }
+
+    let sources = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    };
+    Ok(sources
+        .into_iter()
+        .map(|source| match source {
+            Source::CwRewards(contract) => UnderlyingSource::CwRewards { contract },
+            Source::Source(source) => source,
+        })
+        .collect())
 }
 
 #[cw_serde]
 pub struct InflationConfig {
     /// Where one year is defined as 365 * 24 * 60 * 60 seconds,
     pub rate_per_year: Decimal,
+    /// Protocol fees taken from inflation rewards as they're released, accrued for [`ExecuteMsg::ClaimFees`].
+    #[serde(default)]
+    pub reward_fees: Vec<(Decimal, Addr)>,
+}
+
+/// Limits on NativeToken stake.
+#[cw_serde]
+pub struct StakeLimits {
+    /// The most any one staker can have staked.
+    pub max_per_staker: Option<Uint128>,
+    /// The most that can be staked in total.
+    pub max_total: Option<Uint128>,
+    /// The least a staker can have staked, unless they've unstaked everything.
+    pub min_stake: Option<Uint128>,
+    /// Accept stake up to the caps and refund the excess, instead of rejecting it.
+    #[serde(default)]
+    pub refund_excess: bool,
 }
 
 #[cw_serde]
@@ -117,12 +388,15 @@ pub struct ModuleUpdate<T> {
 #[cw_serde]
 #[derive(Default)]
 pub struct ConfigUpdate {
-    pub owner: Option<Addr>,
     pub staking_cfg: Option<ModuleUpdate<StakingConfig>>,
     pub incentive_cfg: Option<ModuleUpdate<Option<IncentiveConfig>>>,
     pub distribution_cfg: Option<ModuleUpdate<Option<DistributionConfig>>>,
     pub underlying_cfg: Option<ModuleUpdate<Option<UnderlyingConfig>>>,
     pub inflation_cfg: Option<ModuleUpdate<Option<InflationConfig>>>,
+    #[serde(default)]
+    pub stake_limits_cfg: Option<ModuleUpdate<Option<StakeLimits>>>,
+    #[serde(default)]
+    pub forfeit_recipient_cfg: Option<ModuleUpdate<Option<Addr>>>,
 }
 
 #[cw_serde]
@@ -136,3 +410,148 @@ pub struct InflationResponse {
     pub rate_per_year: Decimal,
     pub funds: Option<Coin>,
 }
+
+#[cw_serde]
+pub struct InflationProjectionResponse {
+    pub rate_per_year: Decimal,
+    /// The tokens inflation is emitted on, which after a slash is less than the total stake weight.
+    pub total_staked: Uint128,
+    /// Funds left in the inflation pool, after pending inflation.
+    pub funds: Option<Coin>,
+    /// Projected emissions at the current total staked, capped by the funds left.
+    pub emission_per_day: Uint128,
+    pub emission_per_week: Uint128,
+    pub emission_per_year: Uint128,
+    /// When the inflation pool runs dry at the current total staked. `None` if nothing is being emitted.
+    pub exhausted_at: Option<Timestamp>,
+    /// Projected emissions over the next year, relative to the total staked.
+    pub effective_apr: Decimal,
+}
+
+#[cw_serde]
+pub struct AccruedFeesResponse {
+    pub recipient: Addr,
+    pub fees: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct UnbondingResponse {
+    pub staker: Addr,
+    pub entries: Vec<Unbonding>,
+}
+
+#[cw_serde]
+pub struct Unbonding {
+    pub batch: u64,
+    pub amount: Uint128,
+    /// When the batch finishes unbonding, or `None` while it's still queued.
+    pub release_at: Option<Timestamp>,
+}
+
+#[cw_serde]
+pub struct PrincipalResponse {
+    pub staker: Addr,
+    pub principal: Vec<Coin>,
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub struct ResyncStatus {
+    /// Whether the resync has pages left.
+    pub in_progress: bool,
+    /// The last address reconciled, where the next page starts.
+    pub cursor: Option<String>,
+    /// Addresses reconciled so far.
+    pub synced: u64,
+}
+
+#[cw_serde]
+pub enum OwnershipAction {
+    /// Proposes `new_owner` as the owner, replacing any pending proposal. Only callable by the owner
+    TransferOwnership {
+        new_owner: String,
+        expiry: Option<Expiration>,
+    },
+    /// Accepts the pending proposal. Only callable by the pending owner, before the proposal expires
+    AcceptOwnership,
+    /// Permanently removes the owner, and any pending proposal. Only callable by the owner
+    RenounceOwnership,
+}
+
+#[cw_serde]
+pub struct Ownership {
+    /// `None` once ownership is renounced.
+    pub owner: Option<Addr>,
+    pub pending_owner: Option<Addr>,
+    pub pending_expiry: Option<Expiration>,
+}
+
+/// Administrative roles, each held by the owner and any addresses it's granted to.
+#[cw_serde]
+#[derive(Copy)]
+pub enum Role {
+    /// Updates the config, and rebalances delegations.
+    ConfigAdmin,
+    /// Funds and withdraws from the inflation module.
+    InflationTreasurer,
+    /// Sets weights under Permissioned staking without a quota, and registers and removes weight managers.
+    WeightAdmin,
+    /// Pauses and unpauses operations, and sets the guardian.
+    Pauser,
+    /// Updates the incentive module's whitelist, min size and fee, and nothing else.
+    IncentiveModerator,
+}
+
+#[cw_serde]
+pub struct RolesResponse {
+    pub addr: Addr,
+    pub roles: Vec<Role>,
+}
+
+/// Limits on the weights a weight manager can set under Permissioned staking.
+#[cw_serde]
+pub struct WeightQuota {
+    /// The only stakers the manager can set weights for, if limited.
+    pub stakers: Option<Vec<Addr>>,
+    /// The most weight the manager can grant across all stakers, if limited.
+    pub max_weight: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct WeightManagerResponse {
+    pub manager: Addr,
+    pub quota: WeightQuota,
+    /// Total weight the manager has granted.
+    pub used: Uint128,
+}
+
+#[cw_serde]
+pub struct SlashingResponse {
+    pub slasher: Option<Addr>,
+    /// Tokens held for NativeToken stake.
+    pub pool: Uint128,
+    /// Tokens per unit of stake weight, below one once stake has been slashed.
+    pub exchange_rate: Decimal,
+}
+
+/// Operations that are paused. [`ExecuteMsg::EmergencyUnstake`] can't be paused.
+#[cw_serde]
+#[derive(Default)]
+pub struct PauseFlags {
+    #[serde(default)]
+    pub stake: bool,
+    #[serde(default)]
+    pub unstake: bool,
+    #[serde(default)]
+    pub claim: bool,
+    #[serde(default)]
+    pub distribute: bool,
+    #[serde(default)]
+    pub add_incentive: bool,
+}
+
+#[cw_serde]
+pub struct PauseResponse {
+    pub guardian: Option<Addr>,
+    pub flags: PauseFlags,
+}
@@ -0,0 +1,68 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{ensure, Addr, BlockInfo, StdResult, Storage};
+use cw_storage_plus::Item;
+use cw_utils::Expiration;
+
+use crate::{msg::Ownership, Config, ContractError};
+
+/// An ownership transfer proposed by the owner, waiting to be accepted.
+#[cw_serde]
+struct PendingOwnership {
+    owner: Addr,
+    expiry: Option<Expiration>,
+}
+
+const PENDING: Item<PendingOwnership> = Item::new("pending_ownership");
+
+/// Proposes `new_owner` as the next owner, replacing any pending transfer. The owner stays unchanged until
+/// `new_owner` accepts.
+pub fn transfer(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    new_owner: Addr,
+    expiry: Option<Expiration>,
+) -> Result<(), ContractError> {
+    if let Some(expiry) = &expiry {
+        ensure!(
+            !expiry.is_expired(block),
+            ContractError::InvalidOwnershipExpiry {}
+        );
+    }
+    Ok(PENDING.save(
+        storage,
+        &PendingOwnership {
+            owner: new_owner,
+            expiry,
+        },
+    )?)
+}
+
+/// Completes the pending transfer, if `sender` is the pending owner and it hasn't expired.
+pub fn accept(
+    storage: &mut dyn Storage,
+    block: &BlockInfo,
+    sender: &Addr,
+) -> Result<(), ContractError> {
+    let pending = PENDING
+        .may_load(storage)?
+        .ok_or(ContractError::NoPendingOwnership {})?;
+    ensure!(pending.owner == sender, ContractError::NotPendingOwner {});
+    let expired = matches!(pending.expiry, Some(e) if e.is_expired(block));
+    ensure!(!expired, ContractError::OwnershipTransferExpired {});
+    PENDING.remove(storage);
+    Ok(())
+}
+
+/// Drops the pending transfer, if any.
+pub fn clear(storage: &mut dyn Storage) {
+    PENDING.remove(storage)
+}
+
+pub fn query(storage: &dyn Storage, config: &Config) -> StdResult<Ownership> {
+    let pending = PENDING.may_load(storage)?;
+    Ok(Ownership {
+        owner: config.owner.clone(),
+        pending_owner: pending.as_ref().map(|p| p.owner.clone()),
+        pending_expiry: pending.and_then(|p| p.expiry),
+    })
+}
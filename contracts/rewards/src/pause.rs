@@ -0,0 +1,71 @@
+use cosmwasm_std::{ensure, Addr, StdResult, Storage};
+use cw_rewards_logic::RewardsMsg;
+use cw_storage_plus::Item;
+
+use crate::{
+    msg::{PauseFlags, PauseResponse, Role},
+    roles, Config, ContractError,
+};
+
+const FLAGS: Item<PauseFlags> = Item::new("pause_flags");
+
+/// Who besides pausers can pause and unpause.
+const GUARDIAN: Item<Addr> = Item::new("guardian");
+
+/// Fails with [`ContractError::Paused`] if `paused` is set for `msg`.
+pub fn ensure_active(
+    storage: &dyn Storage,
+    msg: &'static str,
+    paused: impl Fn(&PauseFlags) -> bool,
+) -> Result<(), ContractError> {
+    let flags = FLAGS.may_load(storage)?.unwrap_or_default();
+    ensure!(!paused(&flags), ContractError::Paused(msg));
+    Ok(())
+}
+
+/// Fails if `msg` is paused. Staking or unstaking with `withdraw_rewards` claims too, so it's also
+/// paused along with claiming.
+pub fn ensure_rewards_active(storage: &dyn Storage, msg: &RewardsMsg) -> Result<(), ContractError> {
+    let claims = match msg {
+        RewardsMsg::Stake(msg) => msg.withdraw_rewards,
+        RewardsMsg::Unstake(msg) => msg.withdraw_rewards,
+        _ => false,
+    };
+    if claims {
+        ensure_active(storage, "ClaimRewards", |f| f.claim)?;
+    }
+    match msg {
+        RewardsMsg::Stake(_) => ensure_active(storage, "Stake", |f| f.stake),
+        RewardsMsg::Unstake(_) => ensure_active(storage, "Unstake", |f| f.unstake),
+        RewardsMsg::ClaimRewards(_) => ensure_active(storage, "ClaimRewards", |f| f.claim),
+        RewardsMsg::DistributeRewards(_) => {
+            ensure_active(storage, "DistributeRewards", |f| f.distribute)
+        }
+    }
+}
+
+pub fn can_pause(storage: &dyn Storage, config: &Config, sender: &Addr) -> StdResult<bool> {
+    Ok(roles::has_role(storage, config, Role::Pauser, sender)?
+        || GUARDIAN.may_load(storage)?.as_ref() == Some(sender))
+}
+
+pub fn set_flags(storage: &mut dyn Storage, flags: &PauseFlags) -> StdResult<()> {
+    FLAGS.save(storage, flags)
+}
+
+pub fn set_guardian(storage: &mut dyn Storage, guardian: Option<Addr>) -> StdResult<()> {
+    match guardian {
+        Some(guardian) => GUARDIAN.save(storage, &guardian),
+        None => {
+            GUARDIAN.remove(storage);
+            Ok(())
+        }
+    }
+}
+
+pub fn query(storage: &dyn Storage) -> StdResult<PauseResponse> {
+    Ok(PauseResponse {
+        guardian: GUARDIAN.may_load(storage)?,
+        flags: FLAGS.may_load(storage)?.unwrap_or_default(),
+    })
+}
@@ -0,0 +1,90 @@
+use cosmwasm_std::{coin, Addr, Coin, Decimal, Order, StdResult, Storage, Uint128};
+use cw_storage_plus::Map;
+
+/// Staked principal per staker and denom, under NativeTokens staking.
+const PRINCIPAL: Map<(&Addr, &str), Uint128> = Map::new("principal");
+
+/// Staked principal per denom, across all stakers.
+const TOTALS: Map<&str, Uint128> = Map::new("principal_totals");
+
+pub fn add(storage: &mut dyn Storage, staker: &Addr, stake: &Coin) -> StdResult<()> {
+    PRINCIPAL.update(
+        storage,
+        (staker, &stake.denom),
+        |principal| -> StdResult<_> {
+            Ok(principal.unwrap_or_default().checked_add(stake.amount)?)
+        },
+    )?;
+    TOTALS.update(storage, &stake.denom, |total| -> StdResult<_> {
+        Ok(total.unwrap_or_default().checked_add(stake.amount)?)
+    })?;
+    Ok(())
+}
+
+pub fn sub(storage: &mut dyn Storage, staker: &Addr, unstake: &Coin) -> StdResult<()> {
+    let key = (staker, unstake.denom.as_str());
+    let principal = PRINCIPAL
+        .may_load(storage, key)?
+        .unwrap_or_default()
+        .checked_sub(unstake.amount)?;
+    if principal.is_zero() {
+        PRINCIPAL.remove(storage, key);
+    } else {
+        PRINCIPAL.save(storage, key, &principal)?;
+    }
+    let total = total(storage, &unstake.denom)?.checked_sub(unstake.amount)?;
+    if total.is_zero() {
+        TOTALS.remove(storage, &unstake.denom);
+    } else {
+        TOTALS.save(storage, &unstake.denom, &total)?;
+    }
+    Ok(())
+}
+
+/// The principal staked in `denom` across all stakers.
+pub fn total(storage: &dyn Storage, denom: &str) -> StdResult<Uint128> {
+    Ok(TOTALS.may_load(storage, denom)?.unwrap_or_default())
+}
+
+pub fn all(storage: &dyn Storage, staker: &Addr) -> StdResult<Vec<Coin>> {
+    PRINCIPAL
+        .prefix(staker)
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(denom, amount)| coin(amount.u128(), denom)))
+        .collect()
+}
+
+/// Whether any staker has principal left.
+pub fn any(storage: &dyn Storage) -> StdResult<bool> {
+    Ok(PRINCIPAL
+        .keys_raw(storage, None, None, Order::Ascending)
+        .next()
+        .is_some())
+}
+
+/// Removes and returns every staker's principal.
+pub fn drain(storage: &mut dyn Storage) -> StdResult<Vec<(Addr, Coin)>> {
+    let principal = PRINCIPAL
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|((staker, denom), amount)| (staker, coin(amount.u128(), denom))))
+        .collect::<StdResult<Vec<_>>>()?;
+    PRINCIPAL.clear(storage);
+    TOTALS.clear(storage);
+    Ok(principal)
+}
+
+/// The stake weight of `staker`'s principal, at each denom's multiplier. Denoms that are no longer
+/// configured carry no weight.
+pub fn weight(
+    storage: &dyn Storage,
+    staker: &Addr,
+    denoms: &[(String, Decimal)],
+) -> StdResult<Uint128> {
+    let mut weight = Uint128::zero();
+    for principal in all(storage, staker)? {
+        if let Some((_, multiplier)) = denoms.iter().find(|(d, _)| d == &principal.denom) {
+            weight = weight.checked_add(principal.amount.mul_floor(*multiplier))?;
+        }
+    }
+    Ok(weight)
+}
@@ -1,10 +1,23 @@
-use cosmwasm_std::{Addr, Deps, Env, Order, StdResult, Uint128};
-use cw_rewards_logic::{incentive, inflation, PendingRewardsResponse, StakeInfoResponse};
+use cosmwasm_std::{Addr, Decimal, Deps, Env, Order, StdResult, Uint128};
+use cw_rewards_logic::{
+    fee, incentive, inflation, util::calculate_fee_split, PendingRewardsResponse, StakeInfoResponse,
+};
 use cw_storage_plus::Bound;
 use cw_utils::NativeBalance;
 use kujira::bow::staking::IncentivesResponse;
 
-use crate::{contract::STATE_MACHINE, msg::InflationResponse, Config, ContractError};
+use crate::{
+    contract::STATE_MACHINE,
+    delegation,
+    msg::{
+        AccruedFeesResponse, InflationProjectionResponse, InflationResponse, PrincipalResponse,
+        StakingConfig, UnbondingResponse,
+    },
+    principal, slash, underlying, Config, ContractError,
+};
+
+/// The most entries a page of the role and weight manager listings returns.
+pub const MAX_LIMIT: u32 = 100;
 
 pub fn pending_rewards(
     deps: Deps,
@@ -13,8 +26,8 @@ pub fn pending_rewards(
     staker: Addr,
 ) -> Result<PendingRewardsResponse, ContractError> {
     let mut accrued = STATE_MACHINE.get_accrued(deps.storage, &staker.to_string())?;
-    if let Some(incentive_cfg) = &config.incentive_module {
-        let lri = incentive::get_lri(deps.storage, incentive_cfg.crank_limit, &env.block.time)?;
+    if let Some((crank_limit, fees)) = config.incentive_crank() {
+        let lri = incentive::get_lri_with_fees(deps.storage, crank_limit, &env.block.time, fees)?;
         let (_, lri_user) = STATE_MACHINE
             .calculate_users_rewards(deps.storage, &vec![staker.to_string()], &lri)?
             .pop()
@@ -22,32 +35,32 @@ pub fn pending_rewards(
 
         accrued = (NativeBalance(accrued) + NativeBalance(lri_user)).into_vec();
     }
-    if let Some(underlying_cfg) = &config.underlying_rewards_module {
-        let underlying_rewards: PendingRewardsResponse = deps.querier.query_wasm_smart(
-            &underlying_cfg.underlying_rewards_contract,
-            &crate::msg::QueryMsg::PendingRewards {
-                staker: env.contract.address,
-            },
-        )?;
+    let sources = underlying::sources(&deps.querier, &env, config)?;
+    if !sources.is_empty() {
+        let mut underlying_rewards = NativeBalance::default();
+        for source in sources.iter() {
+            let mut pending = source.pending_rewards(&deps.querier, &env.contract.address)?;
+            calculate_fee_split(&mut pending, underlying::reward_fees(config, source));
+            underlying_rewards += NativeBalance(pending);
+        }
+        let underlying_rewards = underlying_rewards.into_vec();
         let (_, pending_user) = STATE_MACHINE
-            .calculate_users_rewards(
-                deps.storage,
-                &vec![staker.to_string()],
-                &underlying_rewards.rewards,
-            )?
+            .calculate_users_rewards(deps.storage, &vec![staker.to_string()], &underlying_rewards)?
             .pop()
             .unwrap();
         accrued = (NativeBalance(accrued) + NativeBalance(pending_user)).into_vec();
     }
     if let Some(inflation_cfg) = &config.inflation_module {
-        if let Some((inflation, _)) = inflation::pending_inflation(
+        if let Some((inflation, _)) = inflation::pending_inflation_on(
             deps.storage,
-            &STATE_MACHINE,
+            slash::staked(deps.storage)?,
             &inflation_cfg.rate_per_year,
             &env.block.time,
         )? {
+            let mut inflation = vec![inflation];
+            calculate_fee_split(&mut inflation, &inflation_cfg.reward_fees);
             let (_, inflation_user) = STATE_MACHINE
-                .calculate_users_rewards(deps.storage, &vec![staker.to_string()], &vec![inflation])?
+                .calculate_users_rewards(deps.storage, &vec![staker.to_string()], &inflation)?
                 .pop()
                 .unwrap();
             accrued = (NativeBalance(accrued) + NativeBalance(inflation_user)).into_vec();
@@ -114,9 +127,9 @@ pub fn inflation(
     config: &Config,
 ) -> Result<InflationResponse, ContractError> {
     if let Some(inflation_cfg) = &config.inflation_module {
-        let inflation = inflation::pending_inflation(
+        let inflation = inflation::pending_inflation_on(
             deps.storage,
-            &STATE_MACHINE,
+            slash::staked(deps.storage)?,
             &inflation_cfg.rate_per_year,
             &env.block.time,
         )?;
@@ -128,3 +141,67 @@ pub fn inflation(
         Err(ContractError::InflationNotEnabled {})
     }
 }
+
+pub fn inflation_projection(
+    deps: Deps,
+    env: Env,
+    config: &Config,
+) -> Result<InflationProjectionResponse, ContractError> {
+    let inflation_cfg = match &config.inflation_module {
+        Some(cfg) => cfg,
+        None => return Err(ContractError::InflationNotEnabled {}),
+    };
+    let rate = &inflation_cfg.rate_per_year;
+    let total_staked = slash::staked(deps.storage)?;
+    let funds = inflation::pending_inflation_on(deps.storage, total_staked, rate, &env.block.time)?
+        .map(|(_, remaining)| remaining);
+    let available = funds.as_ref().map(|f| f.amount).unwrap_or_default();
+
+    let projected = |seconds| inflation::emission(total_staked, rate, seconds).min(available);
+    let emission_per_year = projected(inflation::YEAR_SECONDS);
+    let effective_apr = if total_staked.is_zero() {
+        Decimal::zero()
+    } else {
+        Decimal::from_ratio(emission_per_year, total_staked)
+    };
+
+    Ok(InflationProjectionResponse {
+        rate_per_year: *rate,
+        total_staked,
+        emission_per_day: projected(inflation::DAY_SECONDS),
+        emission_per_week: projected(inflation::WEEK_SECONDS),
+        emission_per_year,
+        exhausted_at: inflation::exhaustion_time(total_staked, rate, available, &env.block.time),
+        effective_apr,
+        funds,
+    })
+}
+
+pub fn accrued_fees(deps: Deps, recipient: Addr) -> Result<AccruedFeesResponse, ContractError> {
+    let fees = fee::accrued(deps.storage, &recipient)?;
+    Ok(AccruedFeesResponse { recipient, fees })
+}
+
+pub fn unbonding(
+    deps: Deps,
+    env: Env,
+    config: &Config,
+    staker: Addr,
+) -> Result<UnbondingResponse, ContractError> {
+    let entries = match &config.staking_module {
+        StakingConfig::Delegated { denom, .. } => delegation::unbonding(
+            deps.storage,
+            &deps.querier,
+            &env.contract.address,
+            denom,
+            &staker,
+        )?,
+        _ => vec![],
+    };
+    Ok(UnbondingResponse { staker, entries })
+}
+
+pub fn principal(deps: Deps, staker: Addr) -> Result<PrincipalResponse, ContractError> {
+    let principal = principal::all(deps.storage, &staker)?;
+    Ok(PrincipalResponse { staker, principal })
+}
@@ -0,0 +1,62 @@
+use cosmwasm_std::{Addr, CosmosMsg, Env, StdResult, Storage, Uint128};
+use cw_storage_plus::Item;
+use kujira::{DenomMsg, KujiraMsg};
+
+use crate::msg::StakingConfig;
+
+/// Receipts released to stakers by `WithdrawReceipts`, whose stake is held without a stake weight.
+const RELEASED: Item<Uint128> = Item::new("receipts_released");
+
+pub fn released(storage: &dyn Storage) -> StdResult<Uint128> {
+    Ok(RELEASED.may_load(storage)?.unwrap_or_default())
+}
+
+pub fn release(storage: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    let released = released(storage)?.checked_add(amount)?;
+    RELEASED.save(storage, &released)
+}
+
+pub fn redeem(storage: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    let released = released(storage)?.checked_sub(amount)?;
+    RELEASED.save(storage, &released)
+}
+
+/// The tokenfactory denom of the receipt with `subdenom`, created by this contract.
+pub fn denom(env: &Env, subdenom: &str) -> String {
+    format!("factory/{}/{}", env.contract.address, subdenom)
+}
+
+pub fn create(subdenom: &str) -> CosmosMsg<KujiraMsg> {
+    DenomMsg::Create {
+        subdenom: subdenom.into(),
+    }
+    .into()
+}
+
+pub fn mint(env: &Env, subdenom: &str, amount: Uint128, recipient: &Addr) -> CosmosMsg<KujiraMsg> {
+    DenomMsg::Mint {
+        denom: denom(env, subdenom).into(),
+        amount,
+        recipient: recipient.clone(),
+    }
+    .into()
+}
+
+pub fn burn(env: &Env, subdenom: &str, amount: Uint128) -> CosmosMsg<KujiraMsg> {
+    DenomMsg::Burn {
+        denom: denom(env, subdenom).into(),
+        amount,
+    }
+    .into()
+}
+
+/// The receipt subdenom of `staking`, if it mints receipts.
+pub fn subdenom(staking: &StakingConfig) -> Option<&str> {
+    match staking {
+        StakingConfig::NativeToken {
+            receipt: Some(receipt),
+            ..
+        } => Some(receipt),
+        _ => None,
+    }
+}
@@ -0,0 +1,89 @@
+use cosmwasm_std::{Addr, Empty, Order, StdResult, Storage};
+use cw_storage_plus::{Bound, Map};
+
+use crate::{
+    msg::{Role, RolesResponse},
+    query::MAX_LIMIT,
+    Config, ContractError,
+};
+
+const ALL: [Role; 5] = [
+    Role::ConfigAdmin,
+    Role::InflationTreasurer,
+    Role::WeightAdmin,
+    Role::Pauser,
+    Role::IncentiveModerator,
+];
+
+/// Addresses granted each role, besides the owner, who holds every role.
+const MEMBERS: Map<(&str, &Addr), Empty> = Map::new("role_members");
+
+fn key(role: Role) -> &'static str {
+    match role {
+        Role::ConfigAdmin => "config_admin",
+        Role::InflationTreasurer => "inflation_treasurer",
+        Role::WeightAdmin => "weight_admin",
+        Role::Pauser => "pauser",
+        Role::IncentiveModerator => "incentive_moderator",
+    }
+}
+
+pub fn has_role(
+    storage: &dyn Storage,
+    config: &Config,
+    role: Role,
+    addr: &Addr,
+) -> StdResult<bool> {
+    Ok(config.is_owner(addr) || MEMBERS.has(storage, (key(role), addr)))
+}
+
+/// Fails with [`ContractError::Unauthorized`] unless `addr` holds any of `roles`.
+pub fn ensure_any(
+    storage: &dyn Storage,
+    config: &Config,
+    roles: &[Role],
+    addr: &Addr,
+) -> Result<(), ContractError> {
+    for role in roles {
+        if has_role(storage, config, *role, addr)? {
+            return Ok(());
+        }
+    }
+    Err(ContractError::Unauthorized {})
+}
+
+pub fn grant(storage: &mut dyn Storage, role: Role, addr: &Addr) -> StdResult<()> {
+    MEMBERS.save(storage, (key(role), addr), &Empty {})
+}
+
+pub fn revoke(storage: &mut dyn Storage, role: Role, addr: &Addr) {
+    MEMBERS.remove(storage, (key(role), addr))
+}
+
+pub fn query_roles(storage: &dyn Storage, config: &Config, addr: Addr) -> StdResult<RolesResponse> {
+    let mut roles = vec![];
+    for role in ALL {
+        if has_role(storage, config, role, &addr)? {
+            roles.push(role);
+        }
+    }
+    Ok(RolesResponse { addr, roles })
+}
+
+pub fn query_members(
+    storage: &dyn Storage,
+    role: Role,
+    start_after: Option<Addr>,
+    limit: Option<u32>,
+) -> StdResult<Vec<Addr>> {
+    MEMBERS
+        .prefix(key(role))
+        .keys(
+            storage,
+            start_after.as_ref().map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit.unwrap_or(30).min(MAX_LIMIT) as usize)
+        .collect()
+}
@@ -0,0 +1,124 @@
+use cosmwasm_std::{ensure, Addr, Decimal, StdError, StdResult, Storage, Uint128};
+use cw_storage_plus::Item;
+
+use crate::{contract::STATE_MACHINE, msg::SlashingResponse, receipt, ContractError};
+
+/// Tokens held for NativeToken stakers once they've been slashed. Until then stake weights are token
+/// amounts, and after, each weight or released receipt is a share of the pool, so a slash reduces every staker's stake at
+/// once without touching their weights or rewards.
+const POOL: Item<Uint128> = Item::new("slash_pool");
+
+/// Who besides the owner can slash.
+const SLASHER: Item<Addr> = Item::new("slasher");
+
+/// All shares of the pool: stake weights, and the receipts released for them.
+fn supply(storage: &dyn Storage) -> StdResult<Uint128> {
+    Ok(STATE_MACHINE
+        .total_staked(storage)?
+        .checked_add(receipt::released(storage)?)?)
+}
+
+/// The tokens held for all NativeToken stake.
+pub fn pooled(storage: &dyn Storage) -> StdResult<Uint128> {
+    match POOL.may_load(storage)? {
+        Some(pool) => Ok(pool),
+        None => supply(storage),
+    }
+}
+
+/// The tokens all stake weight is worth, which inflation is emitted on. Released receipts earn no rewards,
+/// so they're left out.
+pub fn staked(storage: &dyn Storage) -> StdResult<Uint128> {
+    to_amount(storage, STATE_MACHINE.total_staked(storage)?)
+}
+
+/// The tokens `shares` of stake weight are worth.
+pub fn to_amount(storage: &dyn Storage, shares: Uint128) -> StdResult<Uint128> {
+    match POOL.may_load(storage)? {
+        Some(pool) => {
+            let total = supply(storage)?;
+            Ok(shares.multiply_ratio(pool, total))
+        }
+        None => Ok(shares),
+    }
+}
+
+/// The stake weight `amount` of tokens buys.
+pub fn to_shares(storage: &dyn Storage, amount: Uint128) -> StdResult<Uint128> {
+    match POOL.may_load(storage)? {
+        Some(pool) => {
+            let total = supply(storage)?;
+            amount
+                .checked_multiply_ratio(total, pool)
+                .map_err(|e| StdError::generic_err(e.to_string()))
+        }
+        None => Ok(amount),
+    }
+}
+
+pub fn deposit(storage: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    if let Some(pool) = POOL.may_load(storage)? {
+        POOL.save(storage, &pool.checked_add(amount)?)?;
+    }
+    Ok(())
+}
+
+/// Removes `amount` from the pool, once its shares are unstaked. When no shares are left, weights go back
+/// to being token amounts.
+pub fn withdraw(storage: &mut dyn Storage, amount: Uint128) -> StdResult<()> {
+    if let Some(pool) = POOL.may_load(storage)? {
+        if supply(storage)?.is_zero() {
+            POOL.remove(storage);
+        } else {
+            POOL.save(storage, &pool.checked_sub(amount)?)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn reset(storage: &mut dyn Storage) {
+    POOL.remove(storage)
+}
+
+/// Slashes `ratio` of the pool, returning the amount slashed.
+pub fn slash(storage: &mut dyn Storage, ratio: Decimal) -> Result<Uint128, ContractError> {
+    ensure!(
+        !ratio.is_zero() && ratio < Decimal::one(),
+        ContractError::InvalidSlashRatio {}
+    );
+    let pool = pooled(storage)?;
+    let slashed = pool.mul_floor(ratio);
+    ensure!(
+        !slashed.is_zero() && slashed < pool,
+        ContractError::InvalidSlashRatio {}
+    );
+    POOL.save(storage, &(pool - slashed))?;
+    Ok(slashed)
+}
+
+pub fn can_slash(storage: &dyn Storage, sender: &Addr, owner: Option<&Addr>) -> StdResult<bool> {
+    Ok(owner == Some(sender) || SLASHER.may_load(storage)?.as_ref() == Some(sender))
+}
+
+pub fn set_slasher(storage: &mut dyn Storage, slasher: Option<Addr>) -> StdResult<()> {
+    match slasher {
+        Some(slasher) => SLASHER.save(storage, &slasher),
+        None => {
+            SLASHER.remove(storage);
+            Ok(())
+        }
+    }
+}
+
+pub fn query(storage: &dyn Storage) -> StdResult<SlashingResponse> {
+    let pool = pooled(storage)?;
+    let total = supply(storage)?;
+    Ok(SlashingResponse {
+        slasher: SLASHER.may_load(storage)?,
+        pool,
+        exchange_rate: match total.is_zero() {
+            true => Decimal::one(),
+            false => Decimal::from_ratio(pool, total),
+        },
+    })
+}
@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    coin, Coin, CosmosMsg, DepsMut, Env, StdError, StdResult, Storage, SubMsg, Uint128,
+};
+use cw_rewards_logic::underlying::UnderlyingSource;
+use cw_storage_plus::{Item, Map};
+use kujira::KujiraMsg;
+
+pub const MEASURED_REPLY_ID: u64 = 1;
+
+/// A submessage whose effect on the contract's balances is measured in its reply.
+#[cw_serde]
+pub enum Measured {
+    /// A swap, measured in its ask denom.
+    Swap { ask_denom: String },
+    /// A claim from an underlying source, measured in the denoms it was expected to pay out.
+    Claim {
+        source: UnderlyingSource,
+        denoms: Vec<String>,
+    },
+}
+
+impl Measured {
+    fn denoms(&self) -> Vec<String> {
+        match self {
+            Measured::Swap { ask_denom } => vec![ask_denom.clone()],
+            Measured::Claim { denoms, .. } => denoms.clone(),
+        }
+    }
+}
+
+/// Measured submessages still awaiting a reply, in execution order.
+const PENDING: Item<VecDeque<Measured>> = Item::new("measured_pending");
+/// Contract balance of each tracked denom, as of before the next pending submessage executes.
+const SNAPSHOTS: Map<&str, Uint128> = Map::new("measured_snapshots");
+
+/// Snapshots the balances `measured` is measured in, and returns `msg` as a submessage that replies with
+/// [`MEASURED_REPLY_ID`].
+///
+/// Snapshots are taken when the submessage is created, so measured submessages must execute, in the order
+/// they were created, before any other message that moves funds.
+pub fn measure(
+    deps: &mut DepsMut,
+    env: &Env,
+    measured: Measured,
+    msg: impl Into<CosmosMsg<KujiraMsg>>,
+) -> StdResult<SubMsg<KujiraMsg>> {
+    for denom in measured.denoms() {
+        if !SNAPSHOTS.has(deps.storage, &denom) {
+            let balance = deps.querier.query_balance(&env.contract.address, &denom)?;
+            SNAPSHOTS.save(deps.storage, &denom, &balance.amount)?;
+        }
+    }
+
+    let mut pending = PENDING.may_load(deps.storage)?.unwrap_or_default();
+    pending.push_back(measured);
+    PENDING.save(deps.storage, &pending)?;
+
+    Ok(SubMsg::reply_on_success(msg, MEASURED_REPLY_ID))
+}
+
+/// Pops the submessage that just executed, returning it with the balance it added in each of its denoms.
+///
+/// Every tracked balance is re-snapshotted, since the submessage may have moved more than it's measured in.
+pub fn settle(deps: &mut DepsMut, env: &Env) -> StdResult<(Measured, Vec<Coin>)> {
+    let mut pending = PENDING.load(deps.storage)?;
+    let measured = pending
+        .pop_front()
+        .ok_or_else(|| StdError::not_found("pending measured submessage"))?;
+
+    let mut received = vec![];
+    for denom in measured.denoms() {
+        let snapshot = SNAPSHOTS.load(deps.storage, &denom)?;
+        let balance = deps.querier.query_balance(&env.contract.address, &denom)?;
+        let delta = balance.amount.saturating_sub(snapshot);
+        if !delta.is_zero() {
+            received.push(coin(delta.u128(), denom));
+        }
+    }
+
+    if pending.is_empty() {
+        PENDING.remove(deps.storage);
+        SNAPSHOTS.clear(deps.storage);
+    } else {
+        PENDING.save(deps.storage, &pending)?;
+        let denoms = SNAPSHOTS
+            .keys(deps.storage, None, None, cosmwasm_std::Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for denom in denoms {
+            let balance = deps.querier.query_balance(&env.contract.address, &denom)?;
+            SNAPSHOTS.save(deps.storage, &denom, &balance.amount)?;
+        }
+    }
+
+    Ok((measured, received))
+}
+
+/// Whether every measured submessage has replied.
+pub fn is_settled(storage: &dyn Storage) -> StdResult<bool> {
+    Ok(!PENDING.exists(storage))
+}
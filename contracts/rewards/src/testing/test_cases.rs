@@ -1,11 +1,13 @@
-use cosmwasm_std::{coin, coins, Decimal, Uint128};
+use cosmwasm_std::{coin, coins, to_json_binary, Addr, Binary, Decimal, Empty, Uint128};
+use cw_multi_test::{BankSudo, Executor, StakingSudo, SudoMsg};
+use cw_utils::Expiration;
 use kujira::{bow::staking::IncentivesResponse, Denom, Release, Schedule};
 
-use crate::{msg::*, Config};
-use cw_rewards_logic::*;
+use crate::{msg::*, transition::MAX_MIGRATED_STAKERS, Config};
+use cw_rewards_logic::{underlying::UnderlyingSource, *};
 
 use super::{
-    test_helpers::TestEnv,
+    test_helpers::{incentive_config, inflation_config, MockReceiverMsg, TestEnv},
     test_macros::{create_config, define_test},
 };
 
@@ -16,6 +18,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
     },
@@ -50,7 +56,11 @@ define_test! {
         owner: "owner",
         staking: NativeToken("utoken"),
         distribution: {
-            fees: vec![(Decimal::percent(10), multi_app().api().addr_make("fee_collector"))],
+            fees: vec![(Decimal::percent(10), multi_app().api().addr_make("fee_collector").into())],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
     },
@@ -85,6 +95,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::Some(vec!["utoken".to_string(), "ureward".to_string()]),
         },
     },
@@ -111,6 +125,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         incentive: {
@@ -163,6 +181,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
     },
@@ -172,10 +194,13 @@ define_test! {
     },
     test_fn: |env: &mut TestEnv| {
         env.update_config("owner", ConfigUpdate {
-            owner: Some(env.addr("new_owner")),
             distribution_cfg: Some(ModuleUpdate {
                 update: Some(DistributionConfig {
-                    fees: vec![(Decimal::percent(5), env.addr("fee_collector"))],
+                    fees: vec![(Decimal::percent(5), env.addr("fee_collector").into())],
+                    denom_fees: vec![],
+                    inflation_top_up: None,
+                    conversion: None,
+                    stream: None,
                     whitelisted_denoms: Whitelist::Some(vec!["utoken".to_string()]),
                 }),
             }),
@@ -183,6 +208,8 @@ define_test! {
             staking_cfg: None,
             underlying_cfg: None,
             inflation_cfg: None,
+            stake_limits_cfg: None,
+            forfeit_recipient_cfg: None,
         }).unwrap();
 
         env.stake("alice", coin(500, "utoken")).unwrap();
@@ -195,6 +222,74 @@ define_test! {
     }
 }
 
+define_test! {
+    name: test_ownership,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {},
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        let transfer = |env: &TestEnv, new_owner: &str, expiry: Option<Expiration>| ExecuteMsg::UpdateOwnership(OwnershipAction::TransferOwnership {
+            new_owner: env.addr(new_owner).to_string(),
+            expiry,
+        });
+        let accept = ExecuteMsg::UpdateOwnership(OwnershipAction::AcceptOwnership);
+        let renounce = ExecuteMsg::UpdateOwnership(OwnershipAction::RenounceOwnership);
+
+        // Only the owner can propose, and nothing is pending until they do
+        env.execute("alice", &rewards, transfer(env, "alice", None), vec![]).unwrap_err();
+        let err = env.execute("alice", &rewards, accept.clone(), vec![]).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "No ownership transfer is pending");
+        let expired = Expiration::AtTime(env.block_time());
+        let err = env.execute("owner", &rewards, transfer(env, "alice", Some(expired)), vec![]).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "The ownership transfer expiry has already passed");
+
+        // A proposal expires, and the owner stays unchanged until it's accepted
+        let expiry = Expiration::AtTime(env.block_time().plus_seconds(100));
+        env.execute("owner", &rewards, transfer(env, "alice", Some(expiry)), vec![]).unwrap();
+        let ownership: Ownership = env.query(QueryMsg::Ownership {}).unwrap();
+        assert_eq!(ownership, Ownership {
+            owner: Some(env.addr("owner")),
+            pending_owner: Some(env.addr("alice")),
+            pending_expiry: Some(expiry),
+        });
+        let err = env.execute("bob", &rewards, accept.clone(), vec![]).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Only the pending owner can accept ownership");
+        env.advance_time(100);
+        let err = env.execute("alice", &rewards, accept.clone(), vec![]).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "The ownership transfer has expired");
+
+        // A new proposal replaces the last one
+        env.execute("owner", &rewards, transfer(env, "alice", None), vec![]).unwrap();
+        env.execute("owner", &rewards, transfer(env, "bob", None), vec![]).unwrap();
+        env.execute("alice", &rewards, accept.clone(), vec![]).unwrap_err();
+        env.execute("bob", &rewards, accept.clone(), vec![]).unwrap();
+        let ownership: Ownership = env.query(QueryMsg::Ownership {}).unwrap();
+        assert_eq!(ownership, Ownership { owner: Some(env.addr("bob")), pending_owner: None, pending_expiry: None });
+        env.update_config("owner", ConfigUpdate::default()).unwrap_err();
+        env.update_config("bob", ConfigUpdate::default()).unwrap();
+
+        // Renouncing drops any pending proposal, and locks the owner's operations for good
+        env.execute("bob", &rewards, transfer(env, "alice", None), vec![]).unwrap();
+        env.execute("alice", &rewards, renounce.clone(), vec![]).unwrap_err();
+        env.execute("bob", &rewards, renounce, vec![]).unwrap();
+        env.execute("alice", &rewards, accept, vec![]).unwrap_err();
+        let config: Config = env.query(QueryMsg::Config {}).unwrap();
+        assert_eq!(config.owner, None);
+        env.update_config("bob", ConfigUpdate::default()).unwrap_err();
+    }
+}
+
 define_test! {
     name: test_incentive_without_module,
     config: {
@@ -202,6 +297,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         // Note: No incentive module configured
@@ -229,6 +328,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         incentive: {
@@ -270,6 +373,10 @@ define_test! {
         staking: Permissioned(""),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
     },
@@ -309,6 +416,10 @@ define_test! {
         staking: Permissioned(""),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
     },
@@ -353,6 +464,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::Some(vec!["utoken".to_string(), "ureward".to_string()]),
         },
     },
@@ -382,6 +497,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         incentive: {
@@ -428,6 +547,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::Some(vec!["utoken".to_string(), "ureward".to_string()]),
         },
         incentive: {
@@ -489,6 +612,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         // We'll set the underlying_rewards_module after instantiating the second contract
@@ -509,6 +636,10 @@ fn do_test_underlying_rewards(env: &mut TestEnv) {
         staking: Permissioned(""),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
     };
@@ -522,7 +653,11 @@ fn do_test_underlying_rewards(env: &mut TestEnv) {
         ConfigUpdate {
             underlying_cfg: Some(ModuleUpdate {
                 update: Some(UnderlyingConfig {
-                    underlying_rewards_contract: underlying.clone(),
+                    sources: vec![UnderlyingSource::CwRewards {
+                        contract: underlying.clone(),
+                    }],
+                    forward_stake_to: None,
+                    reward_fees: vec![],
                 }),
             }),
             ..Default::default()
@@ -604,6 +739,10 @@ define_test! {
         staking: NativeToken("utoken"), // Will be updated to Cw4Hook after instantiating the cw4
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
     },
@@ -651,7 +790,7 @@ fn do_test_cw4_hook(env: &mut TestEnv) {
     )
     .unwrap_err();
 
-    // Update the main contract to use the WRONG cw4 address
+    // Update the main contract to use the WRONG cw4 address, which has to be a cw4 group to be listed
     env.update_config(
         "owner",
         ConfigUpdate {
@@ -663,6 +802,31 @@ fn do_test_cw4_hook(env: &mut TestEnv) {
             ..Default::default()
         },
     )
+    .unwrap_err();
+    let wrong_cw4 = env
+        .instantiate(
+            cw4_stake::msg::InstantiateMsg {
+                denom: cw20::Denom::Native("utoken".to_string()),
+                tokens_per_weight: 1u128.into(),
+                min_bond: Uint128::zero(),
+                unbonding_period: cw_utils::Duration::Time(60),
+                admin: Some(env.owner.to_string()),
+            },
+            env.cw4_code_id,
+            "wrong cw4",
+        )
+        .unwrap();
+    env.update_config(
+        "owner",
+        ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate {
+                update: StakingConfig::Cw4Hook {
+                    cw4_addr: wrong_cw4,
+                },
+            }),
+            ..Default::default()
+        },
+    )
     .unwrap();
 
     // Receiving unauthorized hooks should fail
@@ -771,6 +935,10 @@ define_test! {
         staking: NativeToken("utoken"), // Will be updated to DaoDaoHook in the test
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
     },
@@ -796,12 +964,13 @@ fn do_test_daodao_hook(env: &mut TestEnv) {
     .unwrap_err();
 
     // Update the main contract to use the WRONG DAODAO address
+    let wrong_daodao = env.instantiate_daodao();
     env.update_config(
         "owner",
         ConfigUpdate {
             staking_cfg: Some(ModuleUpdate {
                 update: StakingConfig::DaoDaoHook {
-                    daodao_addr: env.addr("wrong_daodao"),
+                    daodao_addr: wrong_daodao,
                 },
             }),
             ..Default::default()
@@ -821,13 +990,14 @@ fn do_test_daodao_hook(env: &mut TestEnv) {
     )
     .unwrap_err();
 
-    // Update the main contract to use the owner as the hook source.
+    // Update the main contract to use the DAODAO address
+    let daodao = env.instantiate_daodao();
     env.update_config(
         "owner",
         ConfigUpdate {
             staking_cfg: Some(ModuleUpdate {
                 update: StakingConfig::DaoDaoHook {
-                    daodao_addr: env.addr("owner"),
+                    daodao_addr: daodao.clone(),
                 },
             }),
             ..Default::default()
@@ -838,22 +1008,22 @@ fn do_test_daodao_hook(env: &mut TestEnv) {
     // Bond tokens for Alice and Bob
     env.execute(
         "owner",
-        &env.rewards_addr.clone(),
-        ExecuteMsg::StakeChangeHook(StakeChangedHookMsg::Stake {
+        &daodao,
+        StakeChangedHookMsg::Stake {
             addr: env.addr("alice"),
             amount: 500u128.into(),
-        }),
+        },
         vec![],
     )
     .unwrap();
 
     env.execute(
         "owner",
-        &env.rewards_addr.clone(),
-        ExecuteMsg::StakeChangeHook(StakeChangedHookMsg::Stake {
+        &daodao,
+        StakeChangedHookMsg::Stake {
             addr: env.addr("bob"),
             amount: 300u128.into(),
-        }),
+        },
         vec![],
     )
     .unwrap();
@@ -883,11 +1053,11 @@ fn do_test_daodao_hook(env: &mut TestEnv) {
     // Unbond some tokens for Alice
     env.execute(
         "owner",
-        &env.rewards_addr.clone(),
-        ExecuteMsg::StakeChangeHook(StakeChangedHookMsg::Unstake {
+        &daodao,
+        StakeChangedHookMsg::Unstake {
             addr: env.addr("alice"),
             amount: 200u128.into(),
-        }),
+        },
         vec![],
     )
     .unwrap();
@@ -917,7 +1087,11 @@ define_test! {
         owner: "owner",
         staking: NativeToken("utoken"),
         distribution: {
-            fees: vec![(Decimal::percent(5), multi_app().api().addr_make("fee_collector"))],
+            fees: vec![(Decimal::percent(5), multi_app().api().addr_make("fee_collector").into())],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         incentive: {
@@ -949,8 +1123,8 @@ define_test! {
 
         // Test QueryMsg::Config
         let config: Config = env.query(QueryMsg::Config {}).unwrap();
-        assert_eq!(config.owner, env.addr("owner"));
-        assert_eq!(config.staking_module, StakingConfig::NativeToken{denom: "utoken".to_string()});
+        assert_eq!(config.owner, Some(env.addr("owner")));
+        assert_eq!(config.staking_module, StakingConfig::NativeToken{denom: "utoken".to_string(), receipt: None});
         assert!(config.distribution_module.is_some());
         assert!(config.incentive_module.is_some());
 
@@ -1015,6 +1189,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         inflation: {
@@ -1084,6 +1262,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         inflation: {
@@ -1145,6 +1327,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         inflation: {
@@ -1178,6 +1364,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         inflation: {
@@ -1209,6 +1399,7 @@ define_test! {
             inflation_cfg: Some(ModuleUpdate {
                 update: Some(InflationConfig {
                     rate_per_year: Decimal::percent(20),
+                    reward_fees: vec![],
                 }),
             }),
             ..Default::default()
@@ -1250,6 +1441,7 @@ define_test! {
             inflation_cfg: Some(ModuleUpdate {
                 update: Some(InflationConfig {
                     rate_per_year: Decimal::percent(5),
+                    reward_fees: vec![],
                 }),
             }),
             ..Default::default()
@@ -1271,6 +1463,10 @@ define_test! {
         staking: NativeToken("utoken"),
         distribution: {
             fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
             whitelisted_denoms: Whitelist::All,
         },
         inflation: {
@@ -1326,3 +1522,2100 @@ define_test! {
         env.query::<InflationResponse>(QueryMsg::Inflation {}).unwrap_err();
     }
 }
+
+define_test! {
+    name: test_inflation_projection_query,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+        inflation: {
+            rate_per_year: Decimal::percent(10),
+        },
+    },
+    accounts: {
+        owner: coins(100_000, "utoken"),
+        alice: coins(1_000_000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        // Nothing staked, so nothing is emitted
+        let projection: InflationProjectionResponse = env.query(QueryMsg::InflationProjection {}).unwrap();
+        assert_eq!(projection.total_staked, Uint128::zero());
+        assert_eq!(projection.funds, None);
+        assert_eq!(projection.emission_per_year, Uint128::zero());
+        assert_eq!(projection.exhausted_at, None);
+        assert_eq!(projection.effective_apr, Decimal::zero());
+
+        // Staked but unfunded, so the pool is already dry
+        env.stake("alice", coin(1_000_000, "utoken")).unwrap();
+        let projection: InflationProjectionResponse = env.query(QueryMsg::InflationProjection {}).unwrap();
+        assert_eq!(projection.emission_per_year, Uint128::zero());
+        assert_eq!(projection.exhausted_at, Some(env.block_time()));
+
+        // Funded for half a year of emissions
+        env.fund_inflation("owner", coin(50_000, "utoken")).unwrap();
+        let projection: InflationProjectionResponse = env.query(QueryMsg::InflationProjection {}).unwrap();
+        assert_eq!(projection.rate_per_year, Decimal::percent(10));
+        assert_eq!(projection.total_staked, Uint128::new(1_000_000));
+        assert_eq!(projection.funds, Some(coin(50_000, "utoken")));
+        assert_eq!(projection.emission_per_day, Uint128::new(273));
+        assert_eq!(projection.emission_per_week, Uint128::new(1917));
+        assert_eq!(projection.emission_per_year, Uint128::new(50_000)); // Capped by the funds left
+        assert_eq!(projection.exhausted_at, Some(env.block_time().plus_seconds(15768000)));
+        assert_eq!(projection.effective_apr, Decimal::percent(5));
+
+        // Runway shrinks as pending inflation is accounted for
+        env.advance_time(7884000); // 3 months
+        let projection: InflationProjectionResponse = env.query(QueryMsg::InflationProjection {}).unwrap();
+        assert_eq!(projection.funds, Some(coin(25_000, "utoken")));
+        assert_eq!(projection.exhausted_at, Some(env.block_time().plus_seconds(7884000)));
+        assert_eq!(projection.effective_apr, Decimal::permille(25));
+
+        // Disable inflation
+        env.update_config("owner", ConfigUpdate {
+            inflation_cfg: Some(ModuleUpdate {
+                update: None,
+            }),
+            ..Default::default()
+        }).unwrap();
+        env.query::<InflationProjectionResponse>(QueryMsg::InflationProjection {}).unwrap_err();
+    }
+}
+
+define_test! {
+    name: test_inflation_top_up,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![(Decimal::percent(10), multi_app().api().addr_make("fee_collector").into())],
+            denom_fees: vec![],
+            inflation_top_up: Some(InflationTopUp {
+                denom: "utoken".to_string(),
+                distribution_share: Decimal::percent(50),
+                incentive_fee_share: Decimal::percent(100),
+            }),
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+        incentive: {
+            crank_limit: 10,
+            min_size: Uint128::new(100),
+            fee: Some(coin(10, "utoken")),
+            whitelisted_denoms: Whitelist::All,
+        },
+        inflation: {
+            rate_per_year: Decimal::zero(),
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        carol: vec![coin(2000, "utoken"), coin(3000, "ureward")],
+        fee_collector: coins(0, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+
+        // 100 to fees, then half of the remaining 900 to the inflation pool
+        env.distribute_rewards("carol", coins(1000, "utoken")).unwrap();
+        env.assert_balance("fee_collector", coin(100, "utoken"));
+        env.assert_pending_rewards("alice", vec![coin(450, "utoken")]);
+        let inflation: InflationResponse = env.query(QueryMsg::Inflation {}).unwrap();
+        assert_eq!(inflation.funds, Some(coin(450, "utoken")));
+
+        // Other denoms aren't routed into the inflation pool
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(450, "utoken"), coin(900, "ureward")]);
+        let inflation: InflationResponse = env.query(QueryMsg::Inflation {}).unwrap();
+        assert_eq!(inflation.funds, Some(coin(450, "utoken")));
+
+        // The incentive fee is routed into the inflation pool
+        let now = env.block_time();
+        env.add_incentive("carol", "ureward", Schedule {
+            start: now,
+            end: now.plus_seconds(3600),
+            amount: Uint128::new(1000),
+            release: Release::Fixed,
+        }, vec![coin(1000, "ureward"), coin(10, "utoken")]).unwrap();
+        let inflation: InflationResponse = env.query(QueryMsg::Inflation {}).unwrap();
+        assert_eq!(inflation.funds, Some(coin(460, "utoken")));
+
+        let top_up = |env: &TestEnv, denom: &str, share: u64| ConfigUpdate {
+            distribution_cfg: Some(ModuleUpdate {
+                update: Some(DistributionConfig {
+                    fees: vec![(Decimal::percent(10), env.addr("fee_collector").into())],
+                    denom_fees: vec![],
+                    inflation_top_up: Some(InflationTopUp {
+                        denom: denom.to_string(),
+                        distribution_share: Decimal::percent(share),
+                        incentive_fee_share: Decimal::percent(100),
+                    }),
+                    conversion: None,
+                    stream: None,
+                    whitelisted_denoms: Whitelist::All,
+                }),
+            }),
+            ..Default::default()
+        };
+
+        // Shares can't exceed one
+        let err = env.update_config("owner", top_up(env, "utoken", 101)).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Inflation top-up shares can't exceed one");
+
+        // A denom the pool doesn't hold is skipped, rather than failing the distribution
+        env.update_config("owner", top_up(env, "ureward", 50)).unwrap();
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(450, "utoken"), coin(1800, "ureward")]);
+        let inflation: InflationResponse = env.query(QueryMsg::Inflation {}).unwrap();
+        assert_eq!(inflation.funds, Some(coin(460, "utoken")));
+
+        // Nothing is routed while the inflation module is disabled
+        env.update_config("owner", top_up(env, "utoken", 50)).unwrap();
+        env.update_config("owner", ConfigUpdate {
+            inflation_cfg: Some(ModuleUpdate { update: None }),
+            ..Default::default()
+        }).unwrap();
+        env.distribute_rewards("carol", coins(500, "utoken")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(900, "utoken"), coin(1800, "ureward")]);
+    }
+}
+
+define_test! {
+    name: test_fee_actions,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        carol: coins(2000, "utoken"),
+        fee_collector: coins(0, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let treasury = env.instantiate(Empty {}, env.receiver_code_id, "treasury").unwrap();
+        let buyback = env.instantiate(Empty {}, env.receiver_code_id, "buyback").unwrap();
+
+        env.update_config("owner", ConfigUpdate {
+            distribution_cfg: Some(ModuleUpdate {
+                update: Some(DistributionConfig {
+                    fees: vec![
+                        (Decimal::percent(10), env.addr("fee_collector").into()),
+                        (Decimal::percent(10), FeeRecipient::Action {
+                            addr: treasury.clone(),
+                            action: FeeAction::Execute {
+                                msg: to_json_binary(&MockReceiverMsg::Deposit {}).unwrap(),
+                            },
+                        }),
+                        (Decimal::percent(5), FeeRecipient::Action {
+                            addr: buyback.clone(),
+                            action: FeeAction::Callback(Binary::from(b"{}").into()),
+                        }),
+                    ],
+                    denom_fees: vec![],
+                    inflation_top_up: None,
+                    conversion: None,
+                    stream: None,
+                    whitelisted_denoms: Whitelist::All,
+                }),
+            }),
+            ..Default::default()
+        }).unwrap();
+
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+        let res = env.distribute_rewards("carol", coins(1000, "utoken")).unwrap();
+
+        env.assert_pending_rewards("alice", vec![coin(750, "utoken")]);
+        env.assert_balance("fee_collector", coin(100, "utoken"));
+        let balance = |addr: &Addr| env.app.wrap().query_balance(addr, "utoken").unwrap();
+        assert_eq!(balance(&treasury), coin(100, "utoken"));
+        assert_eq!(balance(&buyback), coin(50, "utoken"));
+
+        let received = |addr: &Addr| {
+            res.events
+                .iter()
+                .find(|e| e.ty == "wasm" && e.attributes.iter().any(|a| a.value == addr.as_str()))
+                .and_then(|e| e.attributes.iter().find(|a| a.key == "received"))
+                .map(|a| a.value.clone())
+        };
+        assert_eq!(received(&treasury), Some("deposit".to_string()));
+        assert_eq!(received(&buyback), Some("callback".to_string()));
+
+        // Recipients must be valid addresses, whether or not they take an action
+        for recipient in [
+            FeeRecipient::Addr(Addr::unchecked("invalid")),
+            FeeRecipient::Action { addr: Addr::unchecked("invalid"), action: FeeAction::Callback(Binary::from(b"{}").into()) },
+        ] {
+            env.update_config("owner", ConfigUpdate {
+                distribution_cfg: Some(ModuleUpdate {
+                    update: Some(DistributionConfig {
+                        fees: vec![(Decimal::percent(10), recipient)],
+                        denom_fees: vec![],
+                        inflation_top_up: None,
+                        conversion: None,
+                        stream: None,
+                        whitelisted_denoms: Whitelist::All,
+                    }),
+                }),
+                ..Default::default()
+            }).unwrap_err();
+        }
+    }
+}
+
+define_test! {
+    name: test_denom_fees,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![(Decimal::percent(10), multi_app().api().addr_make("fee_collector").into())],
+            denom_fees: vec![
+                ("utoken".to_string(), vec![]),
+                ("upartner".to_string(), vec![(Decimal::percent(5), multi_app().api().addr_make("partner_collector").into())]),
+            ],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        carol: vec![coin(1000, "utoken"), coin(1000, "upartner"), coin(1000, "uother")],
+        fee_collector: coins(0, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+        env.distribute_rewards("carol", vec![coin(1000, "utoken"), coin(1000, "upartner"), coin(1000, "uother")]).unwrap();
+
+        // No fee on the native token, 5% on the partner token, and the fallback 10% on the rest
+        env.assert_pending_rewards("alice", vec![coin(1000, "utoken"), coin(950, "upartner"), coin(900, "uother")]);
+        env.assert_balance("partner_collector", coin(50, "upartner"));
+        env.assert_balance("fee_collector", coin(100, "uother"));
+        env.assert_balance("fee_collector", coin(0, "upartner"));
+
+        // Each schedule can take at most the whole amount, and only pays valid addresses
+        let denom_fees = |env: &TestEnv, fees: Vec<(Decimal, FeeRecipient)>| ConfigUpdate {
+            distribution_cfg: Some(ModuleUpdate {
+                update: Some(DistributionConfig {
+                    fees: vec![(Decimal::percent(10), env.addr("fee_collector").into())],
+                    denom_fees: vec![("upartner".to_string(), fees)],
+                    inflation_top_up: None,
+                    conversion: None,
+                    stream: None,
+                    whitelisted_denoms: Whitelist::All,
+                }),
+            }),
+            ..Default::default()
+        };
+        let over = vec![
+            (Decimal::percent(60), env.addr("partner_collector").into()),
+            (Decimal::percent(50), env.addr("fee_collector").into()),
+        ];
+        let err = env.update_config("owner", denom_fees(env, over)).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Fee rates must sum to at most one");
+        let invalid = vec![(Decimal::percent(5), Addr::unchecked("invalid").into())];
+        env.update_config("owner", denom_fees(env, invalid)).unwrap_err();
+        let full = vec![(Decimal::percent(100), env.addr("partner_collector").into())];
+        env.update_config("owner", denom_fees(env, full)).unwrap();
+    }
+}
+
+define_test! {
+    name: test_protocol_fees,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+        incentive: {
+            crank_limit: 10,
+            min_size: Uint128::new(100),
+            fee: None,
+            whitelisted_denoms: Whitelist::All,
+            reward_fees: vec![(Decimal::percent(10), multi_app().api().addr_make("treasury"))],
+        },
+        inflation: {
+            rate_per_year: Decimal::percent(100),
+            reward_fees: vec![(Decimal::percent(20), multi_app().api().addr_make("treasury"))],
+        },
+    },
+    accounts: {
+        owner: coins(10000, "utoken"),
+        alice: coins(100_000, "utoken"),
+        carol: coins(2000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        env.stake("alice", coin(100_000, "utoken")).unwrap();
+        env.fund_inflation("owner", coin(1000, "utoken")).unwrap();
+
+        let now = env.block_time();
+        env.add_incentive("carol", "ureward", Schedule {
+            start: now,
+            end: now.plus_seconds(3600),
+            amount: Uint128::new(1000),
+            release: Release::Fixed,
+        }, vec![coin(1000, "ureward")]).unwrap();
+
+        env.advance_time(86400);
+        // 1000 ureward released, 10% fee. 273 utoken inflation, 20% fee.
+        env.assert_pending_rewards("alice", vec![coin(900, "ureward"), coin(219, "utoken")]);
+
+        // Fees are only accrued once the rewards are actually cranked
+        let accrued: AccruedFeesResponse = env.query(QueryMsg::AccruedFees { recipient: env.addr("treasury") }).unwrap();
+        assert!(accrued.fees.is_empty());
+        env.claim_fees("treasury").unwrap_err();
+
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(900, "ureward"));
+        env.assert_balance("alice", coin(219, "utoken"));
+
+        let accrued: AccruedFeesResponse = env.query(QueryMsg::AccruedFees { recipient: env.addr("treasury") }).unwrap();
+        assert_eq!(accrued.recipient, env.addr("treasury"));
+        assert_eq!(accrued.fees, vec![coin(100, "ureward"), coin(54, "utoken")]);
+
+        env.claim_fees("treasury").unwrap();
+        env.assert_balance("treasury", coin(100, "ureward"));
+        env.assert_balance("treasury", coin(54, "utoken"));
+
+        let accrued: AccruedFeesResponse = env.query(QueryMsg::AccruedFees { recipient: env.addr("treasury") }).unwrap();
+        assert!(accrued.fees.is_empty());
+        env.claim_fees("treasury").unwrap_err();
+
+        // Fee rates can take at most the whole reward, and only accrue to valid addresses
+        let reward_fees = |fees: Vec<(Decimal, Addr)>| ConfigUpdate {
+            inflation_cfg: Some(ModuleUpdate {
+                update: Some(InflationConfig { reward_fees: fees, ..inflation_config() }),
+            }),
+            ..Default::default()
+        };
+        let over = vec![(Decimal::percent(60), env.addr("treasury")), (Decimal::percent(50), env.addr("owner"))];
+        let err = env.update_config("owner", reward_fees(over)).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Fee rates must sum to at most one");
+        env.update_config("owner", reward_fees(vec![(Decimal::percent(10), Addr::unchecked("invalid"))])).unwrap_err();
+        env.update_config("owner", reward_fees(vec![(Decimal::one(), env.addr("treasury"))])).unwrap();
+    }
+}
+
+define_test! {
+    name: test_underlying_protocol_fees,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        carol: vec![coin(2000, "utoken"), coin(2000, "ureward")],
+    },
+    test_fn: |env: &mut TestEnv| {
+        let underlying_msg = create_config! {
+            app: &env.app,
+            owner: "owner",
+            staking: Permissioned(""),
+            distribution: {
+                fees: vec![],
+                denom_fees: vec![],
+                inflation_top_up: None,
+                conversion: None,
+                stream: None,
+                whitelisted_denoms: Whitelist::All,
+            },
+        };
+        let underlying = env
+            .instantiate(underlying_msg, env.rewards_code_id, "underlying rewards")
+            .unwrap();
+        env.update_config("owner", ConfigUpdate {
+            underlying_cfg: Some(ModuleUpdate {
+                update: Some(UnderlyingConfig {
+                    sources: vec![UnderlyingSource::CwRewards { contract: underlying.clone() }],
+                    forward_stake_to: None,
+                    reward_fees: vec![(Decimal::percent(10), env.addr("treasury"))],
+                }),
+            }),
+            ..Default::default()
+        }).unwrap();
+
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+        env.execute("owner", &underlying, &ExecuteMsg::AdjustWeights {
+            delta: vec![(env.rewards_addr.clone(), 1000u128.into())],
+        }, vec![]).unwrap();
+        env.execute("carol", &underlying, ExecuteMsg::Rewards(RewardsMsg::DistributeRewards(DistributeRewardsMsg {
+            callback: None,
+        })), vec![coin(1000, "ureward")]).unwrap();
+
+        env.assert_pending_rewards("alice", vec![coin(900, "ureward")]);
+
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(900, "ureward"));
+
+        env.claim_fees("treasury").unwrap();
+        env.assert_balance("treasury", coin(100, "ureward"));
+    }
+}
+
+define_test! {
+    name: test_swap_to_target_denom,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        carol: vec![coin(50, "uother"), coin(150, "ureward"), coin(1000, "utoken"), coin(10, "uunrouted")],
+        fee_collector: coins(0, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let reward_swap = env.instantiate_swap("utoken", Decimal::percent(200));
+        let other_swap = env.instantiate_swap("utoken", Decimal::percent(300));
+        let fee_collector = env.addr("fee_collector");
+        let distribution = |routes: Vec<SwapRoute>| DistributionConfig {
+            fees: vec![(Decimal::percent(10), fee_collector.clone().into())],
+            conversion: Some(ConversionConfig {
+                target_denoms: vec!["utoken".to_string()],
+                routes,
+            }),
+            ..crate::testing::test_helpers::distribution_config()
+        };
+        let route = |offer: &str, ask: &str, contract: &Addr| SwapRoute {
+            offer_denom: offer.to_string(),
+            ask_denom: ask.to_string(),
+            contract: contract.clone(),
+            max_spread: Decimal::percent(1),
+        };
+
+        // Routes must end in a target denom, with a spread of at most one
+        env.update_config("owner", ConfigUpdate {
+            distribution_cfg: Some(ModuleUpdate {
+                update: Some(distribution(vec![route("ureward", "uother", &reward_swap)])),
+            }),
+            ..Default::default()
+        }).unwrap_err();
+        let wide = SwapRoute { max_spread: Decimal::percent(101), ..route("ureward", "utoken", &reward_swap) };
+        env.update_config("owner", ConfigUpdate {
+            distribution_cfg: Some(ModuleUpdate { update: Some(distribution(vec![wide])) }),
+            ..Default::default()
+        }).unwrap_err();
+
+        let update = distribution(vec![
+            route("ureward", "utoken", &reward_swap),
+            route("uother", "utoken", &other_swap),
+        ]);
+        env.update_config("owner", ConfigUpdate {
+            distribution_cfg: Some(ModuleUpdate { update: Some(update) }),
+            ..Default::default()
+        }).unwrap();
+
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+
+        // Denoms without a route are rejected
+        env.distribute_rewards("carol", coins(10, "uunrouted")).unwrap_err();
+
+        // Fees are taken in the original denoms, and the rest is swapped into utoken, within the max spread
+        let res = env.distribute_rewards("carol", vec![
+            coin(50, "uother"),
+            coin(100, "ureward"),
+            coin(1000, "utoken"),
+        ]).unwrap();
+        let spreads: Vec<_> = res.events.iter()
+            .flat_map(|e| e.attributes.iter())
+            .filter(|a| a.key == "max_spread")
+            .map(|a| a.value.as_str())
+            .collect();
+        assert_eq!(spreads, vec!["0.01"; 2]);
+        env.assert_pending_rewards("alice", vec![coin(900 + 90 * 2 + 45 * 3, "utoken")]);
+        env.assert_balance("fee_collector", coin(100, "utoken"));
+        env.assert_balance("fee_collector", coin(10, "ureward"));
+        env.assert_balance("fee_collector", coin(5, "uother"));
+
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(1215, "utoken"));
+        env.assert_pending_rewards("alice", vec![]);
+
+        // Only swap proceeds are distributed, even when the contract already holds the ask denom
+        env.distribute_rewards("carol", coins(50, "ureward")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(45 * 2, "utoken")]);
+    }
+}
+
+define_test! {
+    name: test_streamed_distribution,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: Some(StreamConfig { duration: 1000, crank_limit: 10 }),
+            whitelisted_denoms: Whitelist::All,
+        },
+        incentive: {
+            crank_limit: 10,
+            min_size: Uint128::new(1_000_000),
+            fee: Some(coin(100, "ufee")),
+            whitelisted_denoms: Whitelist::Some(vec!["uincentive".to_string()]),
+            reward_fees: vec![(Decimal::percent(10), multi_app().api().addr_make("treasury"))],
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+        carol: coins(1000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+
+        // Nothing is distributed up front, despite the incentive min_size, fee and whitelist
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap();
+        env.assert_pending_rewards("alice", vec![]);
+
+        env.advance_time(500);
+        env.assert_pending_rewards("alice", vec![coin(500, "ureward")]);
+
+        // Staking just before a distribution only earns what's released afterwards
+        env.stake("bob", coin(1000, "utoken")).unwrap();
+        env.advance_time(500);
+        env.assert_pending_rewards("alice", vec![coin(750, "ureward")]);
+        env.assert_pending_rewards("bob", vec![coin(250, "ureward")]);
+
+        env.claim_rewards("alice").unwrap();
+        env.claim_rewards("bob").unwrap();
+        env.assert_balance("alice", coin(750, "ureward"));
+        env.assert_balance("bob", coin(250, "ureward"));
+
+        // Streams are exempt from incentive reward fees
+        let accrued: AccruedFeesResponse = env.query(QueryMsg::AccruedFees { recipient: env.addr("treasury") }).unwrap();
+        assert_eq!(accrued.fees, vec![]);
+    }
+}
+
+define_test! {
+    name: test_streamed_distribution_without_incentives,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: Some(StreamConfig { duration: 100, crank_limit: 10 }),
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        carol: coins(2500, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap();
+
+        env.advance_time(50);
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(500, "ureward"));
+
+        env.advance_time(100);
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(1000, "ureward"));
+        env.assert_pending_rewards("alice", vec![]);
+
+        // A distribution mid-stream folds what's left of the running stream into a new one
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap();
+        env.advance_time(50);
+        env.distribute_rewards("carol", coins(500, "ureward")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(500, "ureward")]);
+        let incentives: IncentivesResponse = env.query(QueryMsg::Incentives { start_after: None, limit: None }).unwrap();
+        assert_eq!(incentives.incentives.len(), 1);
+        assert_eq!(incentives.incentives[0].schedule.amount, Uint128::new(1000));
+
+        env.advance_time(100);
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(2500, "ureward"));
+
+        // Streams need a duration
+        let err = env.update_config("owner", ConfigUpdate {
+            distribution_cfg: Some(ModuleUpdate {
+                update: Some(DistributionConfig {
+                    fees: vec![],
+                    denom_fees: vec![],
+                    inflation_top_up: None,
+                    conversion: None,
+                    stream: Some(StreamConfig { duration: 0, crank_limit: 10 }),
+                    whitelisted_denoms: Whitelist::All,
+                }),
+            }),
+            ..Default::default()
+        }).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Streams need a non-zero duration");
+    }
+}
+
+define_test! {
+    name: test_multiple_underlying_rewards,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+        carol: vec![coin(1000, "ureward"), coin(1000, "uother")],
+    },
+    test_fn: |env: &mut TestEnv| {
+        let mut underlyings = vec![];
+        for label in ["underlying a", "underlying b"] {
+            let underlying_msg = create_config! {
+                app: &env.app,
+                owner: "owner",
+                staking: Permissioned(""),
+                distribution: {
+                    fees: vec![],
+                    denom_fees: vec![],
+                    inflation_top_up: None,
+                    conversion: None,
+                    stream: None,
+                    whitelisted_denoms: Whitelist::All,
+                },
+            };
+            let underlying = env.instantiate(underlying_msg, env.rewards_code_id, label).unwrap();
+            env.execute("owner", &underlying, &ExecuteMsg::AdjustWeights {
+                delta: vec![(env.rewards_addr.clone(), 1000u128.into())],
+            }, vec![]).unwrap();
+            underlyings.push(underlying);
+        }
+
+        // Configs stored before multiple contracts were supported still load
+        let legacy: UnderlyingConfig = cosmwasm_std::from_json(format!(
+            r#"{{"underlying_rewards_contract":"{}","reward_fees":[]}}"#,
+            underlyings[0]
+        )).unwrap();
+        assert_eq!(legacy.sources, vec![UnderlyingSource::CwRewards { contract: underlyings[0].clone() }]);
+
+        env.update_config("owner", ConfigUpdate {
+            underlying_cfg: Some(ModuleUpdate {
+                update: Some(UnderlyingConfig {
+                    sources: underlyings.iter().map(|contract| UnderlyingSource::CwRewards { contract: contract.clone() }).collect(),
+                    forward_stake_to: None,
+                    reward_fees: vec![],
+                }),
+            }),
+            ..Default::default()
+        }).unwrap();
+
+        env.stake("alice", coin(500, "utoken")).unwrap();
+        env.stake("bob", coin(500, "utoken")).unwrap();
+
+        for (underlying, reward) in underlyings.iter().zip([coin(1000, "ureward"), coin(500, "uother")]) {
+            env.execute("carol", underlying, ExecuteMsg::Rewards(RewardsMsg::DistributeRewards(DistributeRewardsMsg {
+                callback: None,
+            })), vec![reward]).unwrap();
+        }
+
+        // Pending rewards aggregate every underlying contract
+        env.assert_pending_rewards("alice", vec![coin(250, "uother"), coin(500, "ureward")]);
+
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(500, "ureward"));
+        env.assert_balance("alice", coin(250, "uother"));
+        env.assert_pending_rewards("bob", vec![coin(250, "uother"), coin(500, "ureward")]);
+
+        // Both underlying contracts were claimed
+        let balance = |denom: &str| env.app.wrap().query_balance(&env.rewards_addr, denom).unwrap();
+        assert_eq!(balance("ureward"), coin(500, "ureward"));
+        assert_eq!(balance("uother"), coin(250, "uother"));
+    }
+}
+
+define_test! {
+    name: test_underlying_rewards_measured,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        // Reports more rewards than it pays out
+        let underlying = env.instantiate_underlying(coins(1000, "ureward"), coins(400, "ureward"));
+        env.update_config("owner", ConfigUpdate {
+            underlying_cfg: Some(ModuleUpdate {
+                update: Some(UnderlyingConfig {
+                    sources: vec![UnderlyingSource::CwRewards { contract: underlying }],
+                    forward_stake_to: None,
+                    reward_fees: vec![(Decimal::percent(10), env.addr("treasury"))],
+                }),
+            }),
+            ..Default::default()
+        }).unwrap();
+
+        env.stake("alice", coin(500, "utoken")).unwrap();
+
+        // Only what actually arrived is distributed, before Bob's stake is applied
+        env.stake("bob", coin(500, "utoken")).unwrap();
+        env.assert_balance("bob", coin(500, "utoken"));
+        let accrued: AccruedFeesResponse = env.query(QueryMsg::AccruedFees { recipient: env.addr("treasury") }).unwrap();
+        assert_eq!(accrued.fees, coins(40, "ureward"));
+
+        // The deferred claim still sees the rewards claimed in the same transaction
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(360 + 180, "ureward"));
+
+        env.unstake("bob", 500).unwrap();
+        env.claim_rewards("bob").unwrap();
+        env.assert_balance("bob", coin(180 + 180, "ureward"));
+        env.assert_balance("bob", coin(1000, "utoken"));
+    }
+}
+
+define_test! {
+    name: test_forward_stake_to_underlying,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        carol: coins(1000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let underlying_msg = create_config! {
+            app: &env.app,
+            owner: "owner",
+            staking: NativeToken("utoken"),
+            distribution: {
+                fees: vec![],
+                denom_fees: vec![],
+                inflation_top_up: None,
+                conversion: None,
+                stream: None,
+                whitelisted_denoms: Whitelist::All,
+            },
+        };
+        let underlying = env.instantiate(underlying_msg, env.rewards_code_id, "underlying rewards").unwrap();
+
+        // Stake can only be forwarded to a configured underlying contract
+        env.update_config("owner", ConfigUpdate {
+            underlying_cfg: Some(ModuleUpdate {
+                update: Some(UnderlyingConfig {
+                    sources: vec![],
+                    forward_stake_to: Some(underlying.clone()),
+                    reward_fees: vec![],
+                }),
+            }),
+            ..Default::default()
+        }).unwrap_err();
+
+        env.update_config("owner", ConfigUpdate {
+            underlying_cfg: Some(ModuleUpdate {
+                update: Some(UnderlyingConfig {
+                    sources: vec![UnderlyingSource::CwRewards { contract: underlying.clone() }],
+                    forward_stake_to: Some(underlying.clone()),
+                    reward_fees: vec![],
+                }),
+            }),
+            ..Default::default()
+        }).unwrap();
+
+        let underlying_stake = |env: &TestEnv| -> Uint128 {
+            let res: StakeInfoResponse = env.app.wrap().query_wasm_smart(&underlying, &QueryMsg::StakeInfo {
+                staker: env.rewards_addr.clone(),
+            }).unwrap();
+            res.amount
+        };
+
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+        env.assert_stake("alice", 1000);
+        assert_eq!(underlying_stake(env), Uint128::new(1000));
+        assert_eq!(env.app.wrap().query_balance(&env.rewards_addr, "utoken").unwrap(), coin(0, "utoken"));
+
+        // Rewards earned upstream flow through to this contract's stakers
+        env.execute("carol", &underlying, ExecuteMsg::Rewards(RewardsMsg::DistributeRewards(DistributeRewardsMsg {
+            callback: None,
+        })), coins(500, "ureward")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(500, "ureward")]);
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(500, "ureward"));
+
+        env.unstake("alice", 400).unwrap();
+        env.assert_balance("alice", coin(400, "utoken"));
+        assert_eq!(underlying_stake(env), Uint128::new(600));
+
+        // Forwarding can't be turned off while stake sits in the underlying contract
+        let no_forwarding = |underlying: &Addr| ConfigUpdate {
+            underlying_cfg: Some(ModuleUpdate {
+                update: Some(UnderlyingConfig {
+                    sources: vec![UnderlyingSource::CwRewards { contract: underlying.clone() }],
+                    forward_stake_to: None,
+                    reward_fees: vec![],
+                }),
+            }),
+            ..Default::default()
+        };
+        let err = env.update_config("owner", no_forwarding(&underlying)).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Stake forwarding can't be changed while tokens are staked");
+
+        env.unstake("alice", 600).unwrap();
+        env.assert_balance("alice", coin(1000, "utoken"));
+        assert_eq!(underlying_stake(env), Uint128::zero());
+        env.update_config("owner", no_forwarding(&underlying)).unwrap();
+    }
+}
+
+define_test! {
+    name: test_underlying_adapters,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let bow = env.instantiate_bow_staking(coins(500, "ureward"));
+        env.add_validator("validator");
+
+        let rewards_addr = env.rewards_addr.clone();
+        env.delegate(&rewards_addr, "validator", 1_000_000);
+
+        env.update_config("owner", ConfigUpdate {
+            underlying_cfg: Some(ModuleUpdate {
+                update: Some(UnderlyingConfig {
+                    sources: vec![
+                        UnderlyingSource::BowStaking { contract: bow, denom: "ulp".into() },
+                        UnderlyingSource::Delegation { validator: "validator".to_string() },
+                    ],
+                    forward_stake_to: None,
+                    reward_fees: vec![],
+                }),
+            }),
+            ..Default::default()
+        }).unwrap();
+
+        env.stake("alice", coin(1000, "utoken")).unwrap();
+        env.advance_time(365 * 24 * 60 * 60);
+
+        // BOW fills and delegation rewards are both pending, at 10% APR on the delegation
+        env.assert_pending_rewards("alice", vec![coin(500, "ureward"), coin(100_000, "ustake")]);
+
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(500, "ureward"));
+        env.assert_balance("alice", coin(100_000, "ustake"));
+
+        // Both sources were claimed, so nothing is left for a new staker
+        env.stake("bob", coin(1000, "utoken")).unwrap();
+        env.assert_pending_rewards("bob", vec![]);
+        env.assert_pending_rewards("alice", vec![]);
+    }
+}
+
+define_test! {
+    name: test_delegated_staking,
+    config: {
+        owner: "owner",
+        staking: NativeToken("ustake"),
+    },
+    accounts: {
+        alice: coins(1000, "ustake"),
+        bob: coins(1000, "ustake"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        env.add_validator("val1");
+        env.add_validator("val2");
+        let delegated = |env: &TestEnv, validator: &str| -> u128 {
+            env.app.wrap()
+                .query_delegation(&env.rewards_addr, validator)
+                .unwrap()
+                .map_or(0, |d| d.amount.amount.u128())
+        };
+
+        // Validator weights have to sum to one
+        let delegated_cfg = |validators: Vec<(&str, Decimal)>| ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate {
+                update: StakingConfig::Delegated {
+                    denom: "ustake".to_string(),
+                    validators: validators.into_iter().map(|(v, w)| (v.to_string(), w)).collect(),
+                    unbonding_period: 60,
+                },
+            }),
+            ..Default::default()
+        };
+        env.update_config("owner", delegated_cfg(vec![("val1", Decimal::percent(75)), ("val2", Decimal::percent(20))])).unwrap_err();
+        env.update_config("owner", delegated_cfg(vec![("val1", Decimal::percent(75)), ("val2", Decimal::percent(25))])).unwrap();
+
+        env.stake("alice", coin(1000, "ustake")).unwrap();
+        env.assert_stake("alice", 1000);
+        assert_eq!(delegated(env, "val1"), 750);
+        assert_eq!(delegated(env, "val2"), 250);
+
+        // Staking rewards accrue at 10% APR, and are claimed before bob's stake changes the delegations
+        env.advance_time(365 * 24 * 60 * 60);
+        env.assert_pending_rewards("alice", vec![coin(100, "ustake")]);
+        env.stake("bob", coin(1000, "ustake")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(100, "ustake")]);
+        env.assert_pending_rewards("bob", vec![]);
+        assert_eq!(delegated(env, "val1"), 1500);
+        assert_eq!(delegated(env, "val2"), 500);
+
+        // Unstaking undelegates from the largest delegation, and the batch can be withdrawn once it's unbonded
+        let rewards = env.rewards_addr.clone();
+        let withdraw = |batch| ExecuteMsg::WithdrawUnbonded { batch };
+        let unbonding = |env: &TestEnv, staker: &str| -> Vec<Unbonding> {
+            let res: UnbondingResponse = env.query(QueryMsg::Unbonding { staker: env.addr(staker) }).unwrap();
+            res.entries
+        };
+        env.unstake("alice", 400).unwrap();
+        env.assert_stake("alice", 600);
+        assert_eq!(delegated(env, "val1"), 1100);
+        assert_eq!(unbonding(env, "alice"), vec![Unbonding {
+            batch: 0,
+            amount: Uint128::new(400),
+            release_at: Some(env.block_time().plus_seconds(60)),
+        }]);
+        let err = env.execute("alice", &rewards, withdraw(0), vec![]).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Unbonding batch 0 hasn't been released yet");
+
+        // Further unstakes are batched, and undelegated together once a seventh of the unbonding period has passed
+        env.unstake("alice", 100).unwrap();
+        env.unstake("bob", 100).unwrap();
+        assert_eq!(delegated(env, "val1"), 1100);
+        assert_eq!(unbonding(env, "bob"), vec![Unbonding { batch: 1, amount: Uint128::new(100), release_at: None }]);
+        let err = env.execute("carol", &rewards, ExecuteMsg::SubmitUnbonding {}, vec![]).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "No unbonding batch is due");
+        env.advance_time(9);
+        env.execute("carol", &rewards, ExecuteMsg::SubmitUnbonding {}, vec![]).unwrap();
+        assert_eq!(delegated(env, "val1"), 900);
+        assert_eq!(unbonding(env, "bob"), vec![Unbonding {
+            batch: 1,
+            amount: Uint128::new(100),
+            release_at: Some(env.block_time().plus_seconds(60)),
+        }]);
+
+        env.advance_time(51);
+        env.execute("alice", &rewards, withdraw(0), vec![]).unwrap();
+        env.assert_balance("alice", coin(400, "ustake"));
+        env.execute("alice", &rewards, withdraw(0), vec![]).unwrap_err();
+        env.execute("alice", &rewards, withdraw(1), vec![]).unwrap_err();
+        env.advance_time(9);
+        env.execute("alice", &rewards, withdraw(1), vec![]).unwrap();
+        env.execute("bob", &rewards, withdraw(1), vec![]).unwrap();
+        env.assert_balance("alice", coin(500, "ustake"));
+        env.assert_balance("bob", coin(100, "ustake"));
+        assert_eq!(unbonding(env, "alice"), vec![]);
+
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(600, "ustake"));
+
+        // Only the owner rebalances, which redelegates everything to match the new weights
+        let rebalance = ExecuteMsg::RebalanceDelegations { validators: vec![("val2".to_string(), Decimal::one())] };
+        env.execute("alice", &rewards, rebalance.clone(), vec![]).unwrap_err();
+        env.execute("owner", &rewards, rebalance, vec![]).unwrap();
+        assert_eq!(delegated(env, "val1"), 0);
+        assert_eq!(delegated(env, "val2"), 1400);
+
+        // Stake weights are shares of the delegations, so a slash is shared by every staker, whoever unstakes first
+        env.app.sudo(SudoMsg::Staking(StakingSudo::Slash {
+            validator: "val2".to_string(),
+            percentage: Decimal::percent(50),
+        })).unwrap();
+        assert_eq!(delegated(env, "val2"), 700);
+        env.unstake("alice", 500).unwrap();
+        env.unstake("bob", 900).unwrap();
+        assert_eq!(unbonding(env, "alice")[0].amount, Uint128::new(250));
+        assert_eq!(unbonding(env, "bob")[0].amount, Uint128::new(450));
+        env.advance_time(9);
+        env.execute("carol", &rewards, ExecuteMsg::SubmitUnbonding {}, vec![]).unwrap();
+        assert_eq!(delegated(env, "val2"), 0);
+
+        env.advance_time(60);
+        env.execute("alice", &rewards, withdraw(2), vec![]).unwrap();
+        env.execute("bob", &rewards, withdraw(3), vec![]).unwrap();
+        env.assert_balance("alice", coin(850, "ustake"));
+        env.assert_balance("bob", coin(550, "ustake"));
+    }
+}
+
+define_test! {
+    name: test_stake_receipts,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        carol: coins(1000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let receipt_cfg = |receipt: &str| ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate {
+                update: StakingConfig::NativeToken {
+                    denom: "utoken".to_string(),
+                    receipt: Some(receipt.to_string()),
+                },
+            }),
+            ..Default::default()
+        };
+        let receipt = format!("factory/{}/receipt", env.rewards_addr);
+        let custody = |env: &TestEnv| env.app.wrap().query_balance(&env.rewards_addr, format!("factory/{}/receipt", env.rewards_addr)).unwrap().amount.u128();
+
+        // Existing stake is minted into the contract's custody when receipts are enabled
+        env.stake("alice", coin(400, "utoken")).unwrap();
+        env.update_config("owner", receipt_cfg("receipt")).unwrap();
+        assert_eq!(custody(env), 400);
+        env.update_config("owner", receipt_cfg("other")).unwrap_err();
+
+        env.stake("alice", coin(600, "utoken")).unwrap();
+        assert_eq!(custody(env), 1000);
+
+        // Withdrawn receipts are liquid, and stop earning rewards
+        let rewards_addr = env.rewards_addr.clone();
+        env.execute("alice", &rewards_addr, ExecuteMsg::WithdrawReceipts { amount: Uint128::new(300) }, vec![]).unwrap();
+        env.assert_balance("alice", coin(300, &receipt));
+        env.assert_stake("alice", 700);
+        env.distribute_rewards("carol", coins(700, "ureward")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(700, "ureward")]);
+
+        // Receipts move weight between holders once deposited
+        env.app.send_tokens(env.addr("alice"), env.addr("bob"), &coins(300, &receipt)).unwrap();
+        env.execute("bob", &rewards_addr, ExecuteMsg::DepositReceipts {}, coins(100, &receipt)).unwrap();
+        env.assert_stake("bob", 100);
+
+        // Unstaking burns receipts, including any sent along
+        env.unstake("bob", 101).unwrap_err();
+        env.execute("bob", &rewards_addr, ExecuteMsg::Rewards(RewardsMsg::Unstake(UnstakeMsg {
+            amount: Uint128::new(300),
+            withdraw_rewards: false,
+            callback: None,
+            denom: None,
+        })), coins(200, &receipt)).unwrap();
+        env.assert_stake("bob", 0);
+        env.assert_balance("bob", coin(300, "utoken"));
+        env.assert_balance("bob", coin(0, &receipt));
+        assert_eq!(custody(env), 700);
+
+        env.unstake("alice", 700).unwrap();
+        env.assert_balance("alice", coin(700, "utoken"));
+        assert_eq!(custody(env), 0);
+    }
+}
+
+define_test! {
+    name: test_multi_denom_staking,
+    config: {
+        owner: "owner",
+        staking: NativeToken("ulp1"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: vec![coin(1000, "ulp1"), coin(1000, "ulp2")],
+        bob: vec![coin(1000, "ulp2"), coin(1000, "utoken")],
+        carol: coins(1000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let denoms_cfg = |denoms: Vec<(&str, Decimal)>| ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate {
+                update: StakingConfig::NativeTokens {
+                    denoms: denoms.into_iter().map(|(d, m)| (d.to_string(), m)).collect(),
+                },
+            }),
+            ..Default::default()
+        };
+        env.update_config("owner", denoms_cfg(vec![("ulp1", Decimal::one()), ("ulp1", Decimal::percent(50))])).unwrap_err();
+        env.update_config("owner", denoms_cfg(vec![("ulp1", Decimal::one()), ("ulp2", Decimal::percent(50))])).unwrap();
+
+        let rewards_addr = env.rewards_addr.clone();
+        let stake = StakeMsg { withdraw_rewards: false, callback: None };
+        let unstake = |amount: u128, denom: Option<&str>| ExecuteMsg::Rewards(RewardsMsg::Unstake(UnstakeMsg {
+            amount: Uint128::new(amount),
+            withdraw_rewards: false,
+            callback: None,
+            denom: denom.map(str::to_string),
+        }));
+
+        // Each denom is weighted by its multiplier, and its principal tracked separately
+        env.execute("alice", &rewards_addr, ExecuteMsg::Rewards(stake.clone().into()), vec![coin(400, "ulp1"), coin(400, "ulp2")]).unwrap();
+        env.assert_stake("alice", 600);
+        let principal: PrincipalResponse = env.query(QueryMsg::Principal { staker: env.addr("alice") }).unwrap();
+        assert_eq!(principal.principal, vec![coin(400, "ulp1"), coin(400, "ulp2")]);
+
+        env.stake("bob", coin(600, "ulp2")).unwrap();
+        env.assert_stake("bob", 300);
+        env.stake("bob", coin(100, "utoken")).unwrap_err();
+
+        env.distribute_rewards("carol", coins(900, "ureward")).unwrap();
+        env.assert_pending_rewards("alice", vec![coin(600, "ureward")]);
+        env.assert_pending_rewards("bob", vec![coin(300, "ureward")]);
+
+        // The denom has to be specified once several are staked
+        env.execute("alice", &rewards_addr, unstake(100, None), vec![]).unwrap_err();
+        env.execute("alice", &rewards_addr, unstake(500, Some("ulp2")), vec![]).unwrap_err();
+        env.execute("alice", &rewards_addr, unstake(100, Some("ulp2")), vec![]).unwrap();
+        env.assert_stake("alice", 550);
+        env.assert_balance("alice", coin(700, "ulp2"));
+
+        env.execute("bob", &rewards_addr, unstake(600, None), vec![]).unwrap();
+        env.assert_stake("bob", 0);
+        env.assert_balance("bob", coin(1000, "ulp2"));
+
+        // New multipliers apply to every staker at once
+        env.update_config("owner", denoms_cfg(vec![("ulp1", Decimal::one()), ("ulp2", Decimal::one())])).unwrap();
+        env.assert_stake("alice", 700);
+        env.stake("alice", coin(100, "ulp1")).unwrap();
+        env.assert_stake("alice", 800);
+
+        // A denom can only be dropped once nothing is staked in it
+        let err = env.update_config("owner", denoms_cfg(vec![("ulp1", Decimal::one())])).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "ulp2 can't be removed from staking while it's staked");
+        env.execute("alice", &rewards_addr, unstake(300, Some("ulp2")), vec![]).unwrap();
+        env.update_config("owner", denoms_cfg(vec![("ulp1", Decimal::one())])).unwrap();
+        env.assert_stake("alice", 500);
+    }
+}
+
+define_test! {
+    name: test_cw4_hook_backfill,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"), // Will be updated to Cw4Hook once the group has members
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let cw4 = env.instantiate(cw4_stake::msg::InstantiateMsg {
+            denom: cw20::Denom::Native("utoken".to_string()),
+            tokens_per_weight: 1u128.into(),
+            min_bond: Uint128::zero(),
+            unbonding_period: cw_utils::Duration::Time(60),
+            admin: Some(env.owner.to_string()),
+        }, env.cw4_code_id, "cw4").unwrap();
+        env.execute("alice", &cw4, &cw4_stake::msg::ExecuteMsg::Bond {}, coins(500, "utoken")).unwrap();
+        env.execute("bob", &cw4, &cw4_stake::msg::ExecuteMsg::Bond {}, coins(300, "utoken")).unwrap();
+
+        // Switching to the group picks up its existing members
+        env.update_config("owner", ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate { update: StakingConfig::Cw4Hook { cw4_addr: cw4.clone() } }),
+            ..Default::default()
+        }).unwrap();
+        env.assert_stake("alice", 500);
+        env.assert_stake("bob", 300);
+
+        // As does instantiating against it
+        let msg = create_config! {
+            app: &env.app,
+            owner: "owner",
+            staking: Cw4Hook(cw4.as_str()),
+        };
+        let rewards = env.instantiate(msg, env.rewards_code_id, "cw4 rewards").unwrap();
+        let stake: StakeInfoResponse = env.app.wrap().query_wasm_smart(&rewards, &QueryMsg::StakeInfo {
+            staker: env.addr("alice"),
+        }).unwrap();
+        assert_eq!(stake.amount, Uint128::new(500));
+    }
+}
+
+define_test! {
+    name: test_resync_weights,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"), // Will be updated to Cw4Hook once the group has members
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+        carol: coins(1000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let resync = |limit| ExecuteMsg::ResyncWeights { start_after: None, limit };
+        let rewards = env.rewards_addr.clone();
+
+        // Nothing to resync without a hook
+        env.execute("carol", &rewards, resync(None), vec![]).unwrap_err();
+
+        let cw4 = env.instantiate(cw4_stake::msg::InstantiateMsg {
+            denom: cw20::Denom::Native("utoken".to_string()),
+            tokens_per_weight: 1u128.into(),
+            min_bond: Uint128::zero(),
+            unbonding_period: cw_utils::Duration::Time(60),
+            admin: Some(env.owner.to_string()),
+        }, env.cw4_code_id, "cw4").unwrap();
+        env.execute("alice", &cw4, &cw4_stake::msg::ExecuteMsg::Bond {}, coins(500, "utoken")).unwrap();
+        env.execute("bob", &cw4, &cw4_stake::msg::ExecuteMsg::Bond {}, coins(300, "utoken")).unwrap();
+
+        // Carol's native stake isn't in the group, so the switch refunds it
+        env.stake("carol", coin(200, "utoken")).unwrap();
+        env.update_config("owner", ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate { update: StakingConfig::Cw4Hook { cw4_addr: cw4.clone() } }),
+            ..Default::default()
+        }).unwrap();
+        env.assert_stake("alice", 500);
+        env.assert_stake("bob", 300);
+        env.assert_stake("carol", 0);
+        env.assert_balance("carol", coin(1000, "utoken"));
+        let status: ResyncStatus = env.query(QueryMsg::ResyncStatus {}).unwrap();
+        assert_eq!(status, ResyncStatus { in_progress: false, cursor: None, synced: 2 });
+
+        // Paging continues from the stored cursor across transactions
+        env.execute("carol", &rewards, resync(Some(1)), vec![]).unwrap();
+        let status: ResyncStatus = env.query(QueryMsg::ResyncStatus {}).unwrap();
+        assert!(status.in_progress);
+        assert_eq!(status.synced, 1);
+        let first = status.cursor.unwrap();
+
+        // A resync in progress can't be steered past stakers it hasn't reached
+        let skip = ExecuteMsg::ResyncWeights { start_after: Some("~".to_string()), limit: Some(1) };
+        env.execute("carol", &rewards, skip, vec![]).unwrap();
+        let status: ResyncStatus = env.query(QueryMsg::ResyncStatus {}).unwrap();
+        assert!(status.in_progress);
+        assert_eq!(status.synced, 2);
+        assert!(status.cursor.unwrap() > first);
+
+        env.execute("carol", &rewards, resync(Some(1)), vec![]).unwrap();
+        let status: ResyncStatus = env.query(QueryMsg::ResyncStatus {}).unwrap();
+        assert_eq!(status, ResyncStatus { in_progress: false, cursor: None, synced: 2 });
+        env.assert_stake("alice", 500);
+        env.assert_stake("bob", 300);
+    }
+}
+
+define_test! {
+    name: test_resync_weights_pages,
+    config: {
+        owner: "owner",
+        staking: Permissioned(""),
+    },
+    accounts: {},
+    test_fn: |env: &mut TestEnv| {
+        let daodao = env.instantiate_daodao();
+        let switch = |env: &mut TestEnv, update| env.update_config("owner", ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate { update }),
+            ..Default::default()
+        }).unwrap();
+        let hook = StakingConfig::DaoDaoHook { daodao_addr: daodao.clone() };
+        let members: Vec<String> = (0..35).map(|i| format!("member{i}")).collect();
+
+        switch(env, hook.clone());
+        for member in members.iter() {
+            env.execute("owner", &daodao, StakeChangedHookMsg::Stake { addr: env.addr(member), amount: Uint128::new(100) }, vec![]).unwrap();
+        }
+        switch(env, StakingConfig::Permissioned {});
+        let zeroed = members.iter().map(|m| (env.addr(m), Uint128::zero())).collect();
+        env.execute("owner", &env.rewards_addr.clone(), ExecuteMsg::SetWeights { weights: zeroed }, vec![]).unwrap();
+
+        // Switching back only syncs the first page
+        switch(env, hook);
+        let status: ResyncStatus = env.query(QueryMsg::ResyncStatus {}).unwrap();
+        assert!(status.in_progress);
+        let unsynced: Vec<&String> = members.iter().filter(|m| env.addr(m).as_str() > status.cursor.as_deref().unwrap()).collect();
+        assert_eq!(unsynced.len(), 5);
+        env.assert_stake(unsynced[0], 0);
+
+        // Stake changes of members not yet synced don't fail the hook source
+        env.execute("owner", &daodao, StakeChangedHookMsg::Unstake { addr: env.addr(unsynced[0]), amount: Uint128::new(40) }, vec![]).unwrap();
+        env.execute("owner", &daodao, StakeChangedHookMsg::Stake { addr: env.addr(unsynced[1]), amount: Uint128::new(40) }, vec![]).unwrap();
+        env.assert_stake(unsynced[0], 0);
+
+        // And are picked up once the resync reaches them
+        env.execute("carol", &env.rewards_addr.clone(), ExecuteMsg::ResyncWeights { start_after: None, limit: None }, vec![]).unwrap();
+        let status: ResyncStatus = env.query(QueryMsg::ResyncStatus {}).unwrap();
+        assert!(!status.in_progress);
+        env.assert_stake(unsynced[0], 60);
+        env.assert_stake(unsynced[1], 140);
+        env.assert_stake(unsynced[2], 100);
+        env.execute("owner", &daodao, StakeChangedHookMsg::Unstake { addr: env.addr(unsynced[0]), amount: Uint128::new(60) }, vec![]).unwrap();
+        env.assert_stake(unsynced[0], 0);
+    }
+}
+
+define_test! {
+    name: test_staking_migration,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: vec![coin(1000, "utoken"), coin(1000, "ulp")],
+        carol: coins(1000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let staking_cfg = |update: StakingConfig| ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate { update }),
+            ..Default::default()
+        };
+        let denoms = vec![("utoken".to_string(), Decimal::one()), ("ulp".to_string(), Decimal::percent(50))];
+
+        env.stake("alice", coin(400, "utoken")).unwrap();
+        env.stake("bob", coin(100, "utoken")).unwrap();
+        env.distribute_rewards("carol", coins(500, "ureward")).unwrap();
+
+        // NativeToken stake is converted into principal of the same denom
+        env.update_config("owner", staking_cfg(StakingConfig::NativeTokens { denoms: denoms.clone() })).unwrap();
+        let principal: PrincipalResponse = env.query(QueryMsg::Principal { staker: env.addr("alice") }).unwrap();
+        assert_eq!(principal.principal, coins(400, "utoken"));
+        env.assert_stake("alice", 400);
+        env.execute("bob", &env.rewards_addr.clone(), ExecuteMsg::Rewards(StakeMsg { withdraw_rewards: false, callback: None }.into()), coins(200, "ulp")).unwrap();
+        env.assert_stake("bob", 200);
+
+        // Principal can't be moved into delegations
+        env.update_config("owner", staking_cfg(StakingConfig::Delegated {
+            denom: "utoken".to_string(),
+            validators: vec![("validator".to_string(), Decimal::one())],
+            unbonding_period: 60,
+        })).unwrap_err();
+
+        // Switching to weights that hold no tokens refunds all principal, and keeps rewards claimable
+        env.update_config("owner", staking_cfg(StakingConfig::Permissioned {})).unwrap();
+        env.assert_stake("alice", 0);
+        env.assert_stake("bob", 0);
+        env.assert_balance("alice", coin(1000, "utoken"));
+        env.assert_balance("bob", coin(1000, "utoken"));
+        env.assert_balance("bob", coin(1000, "ulp"));
+        let principal: PrincipalResponse = env.query(QueryMsg::Principal { staker: env.addr("bob") }).unwrap();
+        assert_eq!(principal.principal, vec![]);
+        env.assert_pending_rewards("alice", coins(400, "ureward"));
+        env.claim_rewards("bob").unwrap();
+        env.assert_balance("bob", coin(100, "ureward"));
+
+        // Weights that aren't backed by tokens can't become native stake
+        env.adjust_weights("owner", vec![("alice", Uint128::new(10))]).unwrap();
+        env.update_config("owner", staking_cfg(StakingConfig::NativeToken { denom: "utoken".to_string(), receipt: None })).unwrap_err();
+        env.assert_stake("alice", 10);
+    }
+}
+
+define_test! {
+    name: test_staking_migration_limit,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+    },
+    accounts: {},
+    test_fn: |env: &mut TestEnv| {
+        let permissioned = ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate { update: StakingConfig::Permissioned {} }),
+            ..Default::default()
+        };
+        let stakers: Vec<String> = (0..=MAX_MIGRATED_STAKERS).map(|i| format!("staker{i}")).collect();
+        for staker in &stakers {
+            env.app.sudo(SudoMsg::Bank(BankSudo::Mint { to_address: env.addr(staker).to_string(), amount: coins(100, "utoken") })).unwrap();
+            env.stake(staker, coin(100, "utoken")).unwrap();
+        }
+
+        // Refunding every staker at once is capped
+        let err = env.update_config("owner", permissioned.clone()).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), format!("Staking can't be switched with more than {MAX_MIGRATED_STAKERS} stakers"));
+
+        env.unstake(&stakers[0], 100).unwrap();
+        env.update_config("owner", permissioned).unwrap();
+        env.assert_balance(&stakers[1], coin(100, "utoken"));
+    }
+}
+
+define_test! {
+    name: test_staking_migration_delegated,
+    config: {
+        owner: "owner",
+        staking: NativeToken("ustake"),
+    },
+    accounts: {
+        alice: coins(1000, "ustake"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        env.add_validator("val1");
+        // Shorter than the chain's unbonding time
+        let delegated = StakingConfig::Delegated {
+            denom: "ustake".to_string(),
+            validators: vec![("val1".to_string(), Decimal::one())],
+            unbonding_period: 30,
+        };
+        let native = StakingConfig::NativeToken { denom: "ustake".to_string(), receipt: None };
+
+        // Staked tokens are delegated as they are
+        env.stake("alice", coin(600, "ustake")).unwrap();
+        env.update_config("owner", ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate { update: delegated }),
+            ..Default::default()
+        }).unwrap();
+        let delegation = env.app.wrap().query_delegation(&env.rewards_addr, "val1").unwrap().unwrap();
+        assert_eq!(delegation.amount, coin(600, "ustake"));
+        env.assert_stake("alice", 600);
+
+        // Bonded tokens can't be returned straight away, nor while they're still unbonding
+        let switch_back = ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate { update: native }),
+            ..Default::default()
+        };
+        env.update_config("owner", switch_back.clone()).unwrap_err();
+        env.unstake("alice", 600).unwrap();
+        env.update_config("owner", switch_back.clone()).unwrap_err();
+
+        // Unbonded tokens are only paid out once they're back in the contract
+        let withdraw = ExecuteMsg::WithdrawUnbonded { batch: 0 };
+        env.advance_time(30);
+        env.execute("alice", &env.rewards_addr.clone(), withdraw.clone(), vec![]).unwrap_err();
+        env.advance_time(30);
+        env.execute("alice", &env.rewards_addr.clone(), withdraw, vec![]).unwrap();
+        env.assert_balance("alice", coin(1000, "ustake"));
+        env.update_config("owner", switch_back).unwrap();
+    }
+}
+
+define_test! {
+    name: test_weight_batches,
+    config: {
+        owner: "owner",
+        staking: Permissioned(""),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        carol: coins(2000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        let batch = |env: &TestEnv, weights: Vec<(&str, u128)>| -> Vec<(Addr, Uint128)> {
+            weights.into_iter().map(|(a, w)| (env.addr(a), Uint128::new(w))).collect()
+        };
+
+        let res = env.execute("owner", &rewards, ExecuteMsg::SetWeights { weights: batch(env, vec![("alice", 500), ("bob", 300)]) }, vec![]).unwrap();
+        assert_eq!(res.events.iter().filter(|e| e.ty == "wasm-rewards/set-weights").count(), 2);
+        env.distribute_rewards("carol", coins(800, "utoken")).unwrap();
+
+        // Deltas apply to current weights, accruing rewards at the old weights first
+        env.execute("owner", &rewards, ExecuteMsg::IncreaseWeights { weights: batch(env, vec![("alice", 100), ("carol", 200)]) }, vec![]).unwrap();
+        env.execute("owner", &rewards, ExecuteMsg::DecreaseWeights { weights: batch(env, vec![("bob", 300)]) }, vec![]).unwrap();
+        env.assert_stake("alice", 600);
+        env.assert_stake("bob", 0);
+        env.assert_stake("carol", 200);
+        env.assert_pending_rewards("bob", coins(300, "utoken"));
+
+        // A bad entry fails the whole batch, naming the address
+        let err = env.execute("owner", &rewards, ExecuteMsg::DecreaseWeights { weights: batch(env, vec![("alice", 100), ("carol", 300)]) }, vec![]).unwrap_err();
+        assert!(err.root_cause().to_string().contains(env.addr("carol").as_str()));
+        env.assert_stake("alice", 600);
+        let err = env.execute("owner", &rewards, ExecuteMsg::IncreaseWeights { weights: batch(env, vec![("alice", 100), ("alice", 100)]) }, vec![]).unwrap_err();
+        assert!(err.root_cause().to_string().contains(env.addr("alice").as_str()));
+        env.execute("owner", &rewards, ExecuteMsg::SetWeights { weights: vec![(Addr::unchecked("not an address"), Uint128::one())] }, vec![]).unwrap_err();
+        env.execute("alice", &rewards, ExecuteMsg::IncreaseWeights { weights: batch(env, vec![("alice", 100)]) }, vec![]).unwrap_err();
+        env.assert_stake("alice", 600);
+    }
+}
+
+define_test! {
+    name: test_weight_managers,
+    config: {
+        owner: "owner",
+        staking: Permissioned(""),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {},
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        let batch = |env: &TestEnv, weights: Vec<(&str, u128)>| -> Vec<(Addr, Uint128)> {
+            weights.into_iter().map(|(a, w)| (env.addr(a), Uint128::new(w))).collect()
+        };
+        let desk_quota = WeightQuota {
+            stakers: Some(vec![env.addr("alice"), env.addr("bob")]),
+            max_weight: Some(Uint128::new(500)),
+        };
+        let unlimited = WeightQuota { stakers: None, max_weight: None };
+
+        // Only the owner registers managers
+        env.execute("desk", &rewards, ExecuteMsg::RegisterWeightManager { manager: env.addr("desk"), quota: unlimited.clone() }, vec![]).unwrap_err();
+        env.execute("owner", &rewards, ExecuteMsg::RegisterWeightManager { manager: env.addr("desk"), quota: desk_quota.clone() }, vec![]).unwrap();
+        env.execute("owner", &rewards, ExecuteMsg::RegisterWeightManager { manager: env.addr("fund"), quota: unlimited }, vec![]).unwrap();
+
+        // Each manager sets its own slice of a staker's weight
+        env.execute("desk", &rewards, ExecuteMsg::SetWeights { weights: batch(env, vec![("alice", 300)]) }, vec![]).unwrap();
+        env.execute("fund", &rewards, ExecuteMsg::IncreaseWeights { weights: batch(env, vec![("alice", 200)]) }, vec![]).unwrap();
+        env.assert_stake("alice", 500);
+
+        // Within its stakers and budget
+        let err = env.execute("desk", &rewards, ExecuteMsg::SetWeights { weights: batch(env, vec![("bob", 300)]) }, vec![]).unwrap_err();
+        assert!(err.root_cause().to_string().contains(env.addr("bob").as_str()));
+        env.execute("desk", &rewards, ExecuteMsg::SetWeights { weights: batch(env, vec![("carol", 100)]) }, vec![]).unwrap_err();
+        env.execute("desk", &rewards, ExecuteMsg::DecreaseWeights { weights: batch(env, vec![("alice", 250)]) }, vec![]).unwrap();
+        env.execute("desk", &rewards, ExecuteMsg::SetWeights { weights: batch(env, vec![("bob", 300)]) }, vec![]).unwrap();
+        env.execute("fund", &rewards, ExecuteMsg::DecreaseWeights { weights: batch(env, vec![("alice", 250)]) }, vec![]).unwrap_err();
+        env.assert_stake("alice", 250);
+        env.assert_stake("bob", 300);
+
+        let desk: WeightManagerResponse = env.query(QueryMsg::WeightManager { manager: env.addr("desk") }).unwrap();
+        assert_eq!(desk.quota, desk_quota);
+        assert_eq!(desk.used, Uint128::new(350));
+        let managers: Vec<WeightManagerResponse> = env.query(QueryMsg::WeightManagers { start_after: None, limit: None }).unwrap();
+        assert_eq!(managers.len(), 2);
+
+        // A slice the owner has since cut below is capped at the staker's weight, releasing the rest of the budget
+        env.execute("owner", &rewards, ExecuteMsg::SetWeights { weights: batch(env, vec![("bob", 100)]) }, vec![]).unwrap();
+        env.execute("desk", &rewards, ExecuteMsg::DecreaseWeights { weights: batch(env, vec![("bob", 100)]) }, vec![]).unwrap();
+        env.assert_stake("bob", 0);
+        let desk: WeightManagerResponse = env.query(QueryMsg::WeightManager { manager: env.addr("desk") }).unwrap();
+        assert_eq!(desk.used, Uint128::new(50));
+
+        // Removed managers lose their rights, but what they granted stays
+        env.execute("owner", &rewards, ExecuteMsg::RemoveWeightManager { manager: env.addr("desk") }, vec![]).unwrap();
+        env.execute("desk", &rewards, ExecuteMsg::SetWeights { weights: batch(env, vec![("alice", 0)]) }, vec![]).unwrap_err();
+        env.assert_stake("alice", 250);
+        env.query::<WeightManagerResponse>(QueryMsg::WeightManager { manager: env.addr("desk") }).unwrap_err();
+
+        // Registered again, a manager can take back what it granted before
+        env.execute("owner", &rewards, ExecuteMsg::RegisterWeightManager { manager: env.addr("desk"), quota: desk_quota }, vec![]).unwrap();
+        let desk: WeightManagerResponse = env.query(QueryMsg::WeightManager { manager: env.addr("desk") }).unwrap();
+        assert_eq!(desk.used, Uint128::new(50));
+        env.execute("desk", &rewards, ExecuteMsg::DecreaseWeights { weights: batch(env, vec![("alice", 50)]) }, vec![]).unwrap();
+        env.assert_stake("alice", 200);
+        let desk: WeightManagerResponse = env.query(QueryMsg::WeightManager { manager: env.addr("desk") }).unwrap();
+        assert_eq!(desk.used, Uint128::zero());
+    }
+}
+
+define_test! {
+    name: test_stake_limits,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+        carol: coins(1000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let limits = |min_stake: u128, refund_excess: bool| ConfigUpdate {
+            stake_limits_cfg: Some(ModuleUpdate { update: Some(StakeLimits {
+                max_per_staker: Some(Uint128::new(500)),
+                max_total: Some(Uint128::new(800)),
+                min_stake: Some(Uint128::new(min_stake)),
+                refund_excess,
+            }) }),
+            ..Default::default()
+        };
+        env.update_config("owner", limits(600, false)).unwrap_err();
+        env.update_config("owner", limits(100, false)).unwrap();
+
+        env.stake("alice", coin(50, "utoken")).unwrap_err();
+        env.stake("alice", coin(600, "utoken")).unwrap_err();
+        env.stake("alice", coin(500, "utoken")).unwrap();
+        env.stake("bob", coin(400, "utoken")).unwrap_err();
+
+        // Stake over the total cap is refunded, until there's no room left
+        env.update_config("owner", limits(100, true)).unwrap();
+        env.stake("bob", coin(400, "utoken")).unwrap();
+        env.assert_stake("bob", 300);
+        env.assert_balance("bob", coin(700, "utoken"));
+        env.stake("carol", coin(100, "utoken")).unwrap_err();
+
+        // Unstaking can't leave less than the minimum behind
+        env.unstake("alice", 450).unwrap_err();
+        env.unstake("alice", 500).unwrap();
+        env.assert_balance("alice", coin(1000, "utoken"));
+        env.stake("carol", coin(100, "utoken")).unwrap();
+    }
+}
+
+define_test! {
+    name: test_stake_limits_receipts,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        let limits = |max_per_staker: Option<u128>| ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate {
+                update: StakingConfig::NativeToken {
+                    denom: "utoken".to_string(),
+                    receipt: Some("receipt".to_string()),
+                },
+            }),
+            stake_limits_cfg: Some(ModuleUpdate { update: Some(StakeLimits {
+                max_per_staker: max_per_staker.map(Uint128::new),
+                max_total: Some(Uint128::new(800)),
+                min_stake: None,
+                refund_excess: true,
+            }) }),
+            ..Default::default()
+        };
+        let err = env.update_config("owner", limits(Some(500))).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "A per-staker stake cap can't be set while stake receipts are minted");
+        env.update_config("owner", limits(None)).unwrap();
+
+        // Stake whose receipts were withdrawn still counts against the total cap
+        env.stake("alice", coin(500, "utoken")).unwrap();
+        env.execute("alice", &rewards, ExecuteMsg::WithdrawReceipts { amount: Uint128::new(500) }, vec![]).unwrap();
+        env.stake("alice", coin(500, "utoken")).unwrap();
+        env.assert_stake("alice", 300);
+        env.assert_balance("alice", coin(200, "utoken"));
+    }
+}
+
+define_test! {
+    name: test_slashing,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+        inflation: {
+            rate_per_year: Decimal::percent(10),
+        },
+    },
+    accounts: {
+        owner: coins(1000, "uinflation"),
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+        carol: coins(1000, "utoken"),
+        dave: coins(1000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        let slash = |env: &TestEnv, ratio: u64| ExecuteMsg::Slash {
+            ratio: Decimal::percent(ratio),
+            recipient: env.addr("treasury"),
+        };
+
+        env.stake("alice", coin(600, "utoken")).unwrap();
+        env.stake("bob", coin(400, "utoken")).unwrap();
+        env.distribute_rewards("dave", coins(1000, "ureward")).unwrap();
+
+        // Only the owner and the slasher can slash, and never all of the stake
+        env.execute("slasher", &rewards, slash(env, 25), vec![]).unwrap_err();
+        env.execute("owner", &rewards, ExecuteMsg::SetSlasher { slasher: Some(env.addr("slasher")) }, vec![]).unwrap();
+        env.execute("slasher", &rewards, slash(env, 100), vec![]).unwrap_err();
+        env.execute("slasher", &rewards, slash(env, 25), vec![]).unwrap();
+        env.assert_balance("treasury", coin(250, "utoken"));
+        let slashing: SlashingResponse = env.query(QueryMsg::Slashing {}).unwrap();
+        assert_eq!(slashing.pool, Uint128::new(750));
+        assert_eq!(slashing.exchange_rate, Decimal::percent(75));
+
+        // Weights, and so rewards, are untouched
+        env.assert_stake("alice", 600);
+        env.assert_pending_rewards("alice", coins(600, "ureward"));
+        env.assert_pending_rewards("bob", coins(400, "ureward"));
+
+        // Inflation is emitted on the tokens left staked
+        env.fund_inflation("owner", coin(1000, "uinflation")).unwrap();
+        let projection: InflationProjectionResponse = env.query(QueryMsg::InflationProjection {}).unwrap();
+        assert_eq!(projection.total_staked, Uint128::new(750));
+        assert_eq!(projection.emission_per_year, Uint128::new(75));
+        env.advance_time(365 * 24 * 60 * 60);
+        let inflation: InflationResponse = env.query(QueryMsg::Inflation {}).unwrap();
+        assert_eq!(inflation.funds, Some(coin(925, "uinflation")));
+        env.claim_rewards("alice").unwrap();
+        env.assert_balance("alice", coin(45, "uinflation"));
+
+        // New stake buys shares at the slashed rate, and unstaking returns what shares are worth
+        env.stake("carol", coin(300, "utoken")).unwrap();
+        env.assert_stake("carol", 400);
+        env.unstake("alice", 600).unwrap();
+        env.assert_balance("alice", coin(850, "utoken"));
+        env.unstake("bob", 400).unwrap();
+        env.unstake("carol", 400).unwrap();
+        env.assert_balance("bob", coin(900, "utoken"));
+        env.assert_balance("carol", coin(1000, "utoken"));
+
+        // Once everything is unstaked, weights are token amounts again
+        let slashing: SlashingResponse = env.query(QueryMsg::Slashing {}).unwrap();
+        assert_eq!(slashing.exchange_rate, Decimal::one());
+        env.stake("carol", coin(300, "utoken")).unwrap();
+        env.assert_stake("carol", 300);
+    }
+}
+
+define_test! {
+    name: test_slashing_receipts,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+        carol: coins(1000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        let receipt = format!("factory/{}/receipt", env.rewards_addr);
+        env.stake("alice", coin(600, "utoken")).unwrap();
+        env.stake("bob", coin(400, "utoken")).unwrap();
+        env.update_config("owner", ConfigUpdate {
+            staking_cfg: Some(ModuleUpdate {
+                update: StakingConfig::NativeToken {
+                    denom: "utoken".to_string(),
+                    receipt: Some("receipt".to_string()),
+                },
+            }),
+            ..Default::default()
+        }).unwrap();
+
+        // Withdrawn receipts are still shares of the pool, so they take their part of a slash
+        env.execute("alice", &rewards, ExecuteMsg::WithdrawReceipts { amount: Uint128::new(600) }, vec![]).unwrap();
+        env.execute("owner", &rewards, ExecuteMsg::Slash { ratio: Decimal::percent(50), recipient: env.addr("treasury") }, vec![]).unwrap();
+        let slashing: SlashingResponse = env.query(QueryMsg::Slashing {}).unwrap();
+        assert_eq!(slashing.pool, Uint128::new(500));
+        assert_eq!(slashing.exchange_rate, Decimal::percent(50));
+        env.unstake("bob", 400).unwrap();
+        env.assert_balance("bob", coin(800, "utoken"));
+
+        // With only receipts left, new stake still buys shares at the slashed rate
+        env.stake("carol", coin(300, "utoken")).unwrap();
+        env.assert_stake("carol", 600);
+        env.execute("alice", &rewards, ExecuteMsg::Rewards(RewardsMsg::Unstake(UnstakeMsg {
+            amount: Uint128::new(600),
+            withdraw_rewards: false,
+            callback: None,
+            denom: None,
+        })), coins(600, &receipt)).unwrap();
+        env.assert_balance("alice", coin(700, "utoken"));
+        env.unstake("carol", 600).unwrap();
+        env.assert_balance("carol", coin(1000, "utoken"));
+    }
+}
+
+define_test! {
+    name: test_pause,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+        carol: coins(2000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        env.stake("alice", coin(500, "utoken")).unwrap();
+        env.stake("bob", coin(500, "utoken")).unwrap();
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap();
+
+        // Only the owner and the guardian can pause
+        let paused = PauseFlags { unstake: true, claim: true, distribute: true, ..Default::default() };
+        env.execute("guardian", &rewards, ExecuteMsg::SetPaused(paused.clone()), vec![]).unwrap_err();
+        env.execute("owner", &rewards, ExecuteMsg::SetGuardian { guardian: Some(env.addr("guardian")) }, vec![]).unwrap();
+        env.execute("guardian", &rewards, ExecuteMsg::SetPaused(paused.clone()), vec![]).unwrap();
+        let pause: PauseResponse = env.query(QueryMsg::Pause {}).unwrap();
+        assert_eq!(pause.flags, paused);
+        assert_eq!(pause.guardian, Some(env.addr("guardian")));
+
+        let err = env.unstake("alice", 500).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "Unstake is paused");
+        env.claim_rewards("alice").unwrap_err();
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap_err();
+        env.stake("bob", coin(100, "utoken")).unwrap();
+
+        // Emergency unstaking still returns principal, forfeiting unsettled rewards
+        env.execute("alice", &rewards, ExecuteMsg::EmergencyUnstake {}, vec![]).unwrap();
+        env.assert_balance("alice", coin(1000, "utoken"));
+        env.assert_stake("alice", 0);
+        env.assert_pending_rewards("alice", vec![]);
+        env.execute("alice", &rewards, ExecuteMsg::EmergencyUnstake {}, vec![]).unwrap_err();
+
+        // Withdrawing rewards along with a stake change is claiming too
+        env.execute("owner", &rewards, ExecuteMsg::SetPaused(PauseFlags { claim: true, ..Default::default() }), vec![]).unwrap();
+        let unstake = ExecuteMsg::Rewards(RewardsMsg::Unstake(UnstakeMsg {
+            amount: Uint128::new(100),
+            withdraw_rewards: true,
+            callback: None,
+            denom: None,
+        }));
+        let err = env.execute("bob", &rewards, unstake, vec![]).unwrap_err();
+        assert_eq!(err.root_cause().to_string(), "ClaimRewards is paused");
+        let stake = ExecuteMsg::Rewards(RewardsMsg::Stake(StakeMsg { withdraw_rewards: true, callback: None }));
+        env.execute("bob", &rewards, stake, coins(100, "utoken")).unwrap_err();
+        env.unstake("bob", 100).unwrap();
+        env.assert_balance("bob", coin(0, "ureward"));
+
+        env.execute("owner", &rewards, ExecuteMsg::SetPaused(PauseFlags::default()), vec![]).unwrap();
+        // Alice's forfeited rewards are redistributed to bob, less rounding
+        env.claim_rewards("bob").unwrap();
+        env.assert_balance("bob", coin(999, "ureward"));
+    }
+}
+
+define_test! {
+    name: test_emergency_unstake_forfeit,
+    config: {
+        owner: "owner",
+        staking: NativeToken("utoken"),
+        distribution: {
+            fees: vec![],
+            denom_fees: vec![],
+            inflation_top_up: None,
+            conversion: None,
+            stream: None,
+            whitelisted_denoms: Whitelist::All,
+        },
+    },
+    accounts: {
+        alice: coins(1000, "utoken"),
+        bob: coins(1000, "utoken"),
+        carol: coins(2000, "ureward"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        env.update_config("owner", ConfigUpdate {
+            forfeit_recipient_cfg: Some(ModuleUpdate { update: Some(env.addr("treasury")) }),
+            ..Default::default()
+        }).unwrap();
+
+        env.stake("alice", coin(500, "utoken")).unwrap();
+        env.stake("bob", coin(500, "utoken")).unwrap();
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap();
+
+        // Forfeited rewards are accrued to the forfeit recipient instead
+        env.execute("alice", &rewards, ExecuteMsg::EmergencyUnstake {}, vec![]).unwrap();
+        env.assert_balance("alice", coin(1000, "utoken"));
+        env.assert_balance("alice", coin(0, "ureward"));
+        let accrued: AccruedFeesResponse = env.query(QueryMsg::AccruedFees { recipient: env.addr("treasury") }).unwrap();
+        assert_eq!(accrued.fees, coins(500, "ureward"));
+        env.claim_fees("treasury").unwrap();
+        env.assert_balance("treasury", coin(500, "ureward"));
+
+        env.claim_rewards("bob").unwrap();
+        env.assert_balance("bob", coin(500, "ureward"));
+
+        // With no stakers left, nothing is redistributed
+        env.update_config("owner", ConfigUpdate {
+            forfeit_recipient_cfg: Some(ModuleUpdate { update: None }),
+            ..Default::default()
+        }).unwrap();
+        env.distribute_rewards("carol", coins(1000, "ureward")).unwrap();
+        env.execute("bob", &rewards, ExecuteMsg::EmergencyUnstake {}, vec![]).unwrap();
+        env.assert_balance("bob", coin(1000, "utoken"));
+        env.assert_balance("bob", coin(500, "ureward"));
+        env.assert_stake("bob", 0);
+    }
+}
+
+define_test! {
+    name: test_roles,
+    config: {
+        owner: "owner",
+        staking: Permissioned(""),
+        incentive: {
+            crank_limit: 10,
+        },
+        inflation: {
+            rate_per_year: Decimal::percent(10),
+        },
+    },
+    accounts: {
+        treasurer: coins(1000, "utoken"),
+    },
+    test_fn: |env: &mut TestEnv| {
+        let rewards = env.rewards_addr.clone();
+        let grant = |env: &TestEnv, role, addr: &str| ExecuteMsg::GrantRole { role, addr: env.addr(addr) };
+        let moderate = |cfg: IncentiveConfig| ConfigUpdate {
+            incentive_cfg: Some(ModuleUpdate { update: Some(cfg) }),
+            ..Default::default()
+        };
+        let min_size = IncentiveConfig { min_size: Uint128::new(100), ..incentive_config() };
+
+        // Without roles, nothing privileged is allowed
+        env.fund_inflation("treasurer", coin(500, "utoken")).unwrap_err();
+        env.adjust_weights("manager", vec![("alice", Uint128::new(100))]).unwrap_err();
+        env.execute("pauser", &rewards, ExecuteMsg::SetPaused(PauseFlags::default()), vec![]).unwrap_err();
+        env.update_config("moderator", moderate(min_size.clone())).unwrap_err();
+
+        // Only the owner grants roles, which it holds all of
+        env.execute("admin", &rewards, grant(env, Role::ConfigAdmin, "admin"), vec![]).unwrap_err();
+        let owner: RolesResponse = env.query(QueryMsg::Roles { addr: env.addr("owner") }).unwrap();
+        assert_eq!(owner.roles.len(), 5);
+        env.execute("owner", &rewards, grant(env, Role::ConfigAdmin, "admin"), vec![]).unwrap();
+        env.execute("owner", &rewards, grant(env, Role::InflationTreasurer, "treasurer"), vec![]).unwrap();
+        env.execute("owner", &rewards, grant(env, Role::WeightAdmin, "manager"), vec![]).unwrap();
+        env.execute("owner", &rewards, grant(env, Role::Pauser, "pauser"), vec![]).unwrap();
+        env.execute("owner", &rewards, grant(env, Role::IncentiveModerator, "moderator"), vec![]).unwrap();
+        let roles: RolesResponse = env.query(QueryMsg::Roles { addr: env.addr("manager") }).unwrap();
+        assert_eq!(roles.roles, vec![Role::WeightAdmin]);
+        let members: Vec<Addr> = env.query(QueryMsg::RoleMembers { role: Role::Pauser, start_after: None, limit: None }).unwrap();
+        assert_eq!(members, vec![env.addr("pauser")]);
+
+        // Each role is limited to its own operations
+        env.fund_inflation("treasurer", coin(500, "utoken")).unwrap();
+        env.withdraw_inflation("treasurer", 100).unwrap();
+        env.assert_balance("treasurer", coin(600, "utoken"));
+        env.withdraw_inflation("manager", 100).unwrap_err();
+
+        env.adjust_weights("manager", vec![("alice", Uint128::new(100))]).unwrap();
+        env.assert_stake("alice", 100);
+        env.adjust_weights("pauser", vec![("alice", Uint128::new(200))]).unwrap_err();
+
+        env.execute("pauser", &rewards, ExecuteMsg::SetPaused(PauseFlags { stake: true, ..Default::default() }), vec![]).unwrap();
+        env.execute("pauser", &rewards, ExecuteMsg::SetPaused(PauseFlags::default()), vec![]).unwrap();
+        env.update_config("pauser", ConfigUpdate::default()).unwrap_err();
+
+        // Incentive moderators can only update the incentive whitelist, min size and fee
+        env.update_config("moderator", moderate(min_size.clone())).unwrap();
+        let config: Config = env.query(QueryMsg::Config {}).unwrap();
+        assert_eq!(config.incentive_module.unwrap().min_size, Uint128::new(100));
+        let reward_fees = IncentiveConfig { reward_fees: vec![(Decimal::one(), env.addr("moderator"))], ..min_size.clone() };
+        env.update_config("moderator", moderate(reward_fees)).unwrap_err();
+        env.update_config("moderator", moderate(IncentiveConfig { crank_limit: 20, ..min_size.clone() })).unwrap_err();
+        env.update_config("moderator", ConfigUpdate { incentive_cfg: Some(ModuleUpdate { update: None }), ..Default::default() }).unwrap_err();
+        env.update_config("moderator", ConfigUpdate { inflation_cfg: Some(ModuleUpdate { update: None }), ..moderate(min_size.clone()) }).unwrap_err();
+        env.update_config("admin", ConfigUpdate { inflation_cfg: Some(ModuleUpdate { update: None }), ..moderate(IncentiveConfig { crank_limit: 20, ..min_size }) }).unwrap();
+        env.execute("admin", &rewards, grant(env, Role::Pauser, "admin"), vec![]).unwrap_err();
+
+        // Revoked roles no longer authorize anything
+        env.execute("owner", &rewards, ExecuteMsg::RevokeRole { role: Role::WeightAdmin, addr: env.addr("manager") }, vec![]).unwrap();
+        env.adjust_weights("manager", vec![("alice", Uint128::new(200))]).unwrap_err();
+        let roles: RolesResponse = env.query(QueryMsg::Roles { addr: env.addr("manager") }).unwrap();
+        assert!(roles.roles.is_empty());
+    }
+}
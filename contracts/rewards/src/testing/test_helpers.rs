@@ -1,33 +1,49 @@
 use std::fmt::Debug;
 
 use crate::msg::*;
-use cosmwasm_std::{Addr, Coin, Empty, StdResult, Timestamp, Uint128};
-use cw_multi_test::{App, AppResponse, Contract, ContractWrapper, Executor};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::testing::{MockApi, MockStorage};
+use cosmwasm_std::{
+    coin, coins, to_json_binary, wasm_execute, Addr, Api, BankMsg, Binary, BlockInfo, Coin,
+    CustomMsg, CustomQuery, Decimal, Deps, DepsMut, Empty, MessageInfo, Order, Querier, Response,
+    StakingMsg, StdError, StdResult, Storage, Timestamp, Uint128, Validator,
+};
+use cw_multi_test::{
+    App, AppResponse, BankKeeper, BankSudo, BasicAppBuilder, Contract, ContractWrapper,
+    CosmosRouter, Executor, Module, StakingInfo, SudoMsg, WasmKeeper,
+};
+use cw_storage_plus::{Bound, Item, Map};
 use cw_utils::NativeBalance;
-use kujira::Schedule;
+use kujira::{bow::staking as bow, fin, CallbackMsg, DenomMsg, KujiraMsg, Schedule};
 use cw_rewards_logic::*;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 pub struct TestEnv {
-    pub app: App,
+    pub app: KujiraApp,
     pub owner: Addr,
     pub rewards_addr: Addr,
     pub rewards_code_id: u64,
     pub cw4_code_id: u64,
+    pub receiver_code_id: u64,
+    pub swap_code_id: u64,
+    pub underlying_code_id: u64,
+    pub bow_code_id: u64,
+    pub daodao_code_id: u64,
 }
 
-pub fn contract_rewards() -> Box<dyn Contract<Empty>> {
+pub fn contract_rewards() -> Box<dyn Contract<KujiraMsg>> {
     let contract = ContractWrapper::new(
         crate::contract::execute,
         crate::contract::instantiate,
         crate::contract::query,
-    );
+    )
+    .with_reply(crate::contract::reply);
     Box::new(contract)
 }
 
-pub fn contract_cw4() -> Box<dyn Contract<Empty>> {
-    let contract = ContractWrapper::new(
+pub fn contract_cw4() -> Box<dyn Contract<KujiraMsg>> {
+    let contract = ContractWrapper::new_with_empty(
         cw4_stake::contract::execute,
         cw4_stake::contract::instantiate,
         cw4_stake::contract::query,
@@ -35,18 +51,291 @@ pub fn contract_cw4() -> Box<dyn Contract<Empty>> {
     Box::new(contract)
 }
 
-pub fn multi_app() -> App {
-    App::default()
+#[cw_serde]
+pub enum MockReceiverMsg {
+    Deposit {},
+    Callback(CallbackMsg),
+}
+
+/// Accepts deposits and kujira callbacks, tagging the response with the received message.
+pub fn contract_mock_receiver() -> Box<dyn Contract<KujiraMsg>> {
+    let contract = ContractWrapper::new_with_empty(
+        |_, _, info: MessageInfo, msg: MockReceiverMsg| -> StdResult<Response> {
+            let received = match msg {
+                MockReceiverMsg::Deposit {} => "deposit",
+                MockReceiverMsg::Callback(_) => "callback",
+            };
+            Ok(Response::new().add_attributes(vec![
+                ("received", received.to_string()),
+                ("funds", NativeBalance(info.funds).to_string()),
+            ]))
+        },
+        |_, _, _, _: Empty| -> StdResult<Response> { Ok(Response::default()) },
+        |_, _, _: Empty| -> StdResult<Binary> { to_json_binary(&Empty {}) },
+    );
+    Box::new(contract)
+}
+
+#[cw_serde]
+pub struct MockSwapInstantiateMsg {
+    pub ask_denom: String,
+    pub price: Decimal,
+}
+
+const MOCK_SWAP: Item<MockSwapInstantiateMsg> = Item::new("mock_swap");
+
+/// Swaps any offered funds into `ask_denom` at a fixed `price`, through the FIN `Swap` interface, echoing
+/// the `max_spread` it was given.
+pub fn contract_mock_swap() -> Box<dyn Contract<KujiraMsg>> {
+    let contract = ContractWrapper::new_with_empty(
+        |deps: DepsMut, _, info: MessageInfo, msg: fin::ExecuteMsg| -> StdResult<Response> {
+            let fin::ExecuteMsg::Swap { max_spread, .. } = msg else {
+                return Err(StdError::generic_err("unsupported"));
+            };
+            let cfg = MOCK_SWAP.load(deps.storage)?;
+            let offer =
+                cw_utils::one_coin(&info).map_err(|e| StdError::generic_err(e.to_string()))?;
+            let ask = coin(offer.amount.mul_floor(cfg.price).u128(), cfg.ask_denom);
+            Ok(Response::new()
+                .add_message(BankMsg::Send {
+                    to_address: info.sender.to_string(),
+                    amount: vec![ask],
+                })
+                .add_attribute("max_spread", max_spread.unwrap_or_default().to_string()))
+        },
+        |deps: DepsMut, _, _, msg: MockSwapInstantiateMsg| -> StdResult<Response> {
+            MOCK_SWAP.save(deps.storage, &msg)?;
+            Ok(Response::default())
+        },
+        |_, _, _: Empty| -> StdResult<Binary> { to_json_binary(&Empty {}) },
+    );
+    Box::new(contract)
+}
+
+#[cw_serde]
+pub struct MockUnderlyingInstantiateMsg {
+    /// Rewards reported by `PendingRewards`.
+    pub reported: Vec<Coin>,
+    /// Rewards actually sent on `ClaimRewards`.
+    pub paid: Vec<Coin>,
+}
+
+const MOCK_UNDERLYING: Item<MockUnderlyingInstantiateMsg> = Item::new("mock_underlying");
+
+/// An underlying rewards contract that reports one amount of pending rewards, but pays out another.
+pub fn contract_mock_underlying() -> Box<dyn Contract<KujiraMsg>> {
+    let contract = ContractWrapper::new_with_empty(
+        |deps: DepsMut, _, info: MessageInfo, _: ExecuteMsg| -> StdResult<Response> {
+            let cfg = MOCK_UNDERLYING.load(deps.storage)?;
+            Ok(Response::new().add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: cfg.paid,
+            }))
+        },
+        |deps: DepsMut, _, _, msg: MockUnderlyingInstantiateMsg| -> StdResult<Response> {
+            MOCK_UNDERLYING.save(deps.storage, &msg)?;
+            Ok(Response::default())
+        },
+        |deps: Deps, _, _: QueryMsg| -> StdResult<Binary> {
+            let cfg = MOCK_UNDERLYING.load(deps.storage)?;
+            to_json_binary(&PendingRewardsResponse {
+                rewards: cfg.reported,
+            })
+        },
+    );
+    Box::new(contract)
+}
+
+const MOCK_BOW_FILLS: Item<Vec<Coin>> = Item::new("mock_bow_fills");
+
+/// A BOW staking contract with a fixed set of fills, paid out on the first claim.
+pub fn contract_mock_bow_staking() -> Box<dyn Contract<KujiraMsg>> {
+    let contract = ContractWrapper::new_with_empty(
+        |deps: DepsMut, _, info: MessageInfo, msg: bow::ExecuteMsg| -> StdResult<Response> {
+            let bow::ExecuteMsg::Claim { .. } = msg else {
+                return Err(StdError::generic_err("unsupported"));
+            };
+            let fills = MOCK_BOW_FILLS.load(deps.storage)?;
+            MOCK_BOW_FILLS.save(deps.storage, &vec![])?;
+            Ok(Response::new().add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: fills,
+            }))
+        },
+        |deps: DepsMut, _, _, fills: Vec<Coin>| -> StdResult<Response> {
+            MOCK_BOW_FILLS.save(deps.storage, &fills)?;
+            Ok(Response::default())
+        },
+        |deps: Deps, _, msg: bow::QueryMsg| -> StdResult<Binary> {
+            let bow::QueryMsg::Fills { .. } = msg else {
+                return Err(StdError::generic_err("unsupported"));
+            };
+            to_json_binary(&MOCK_BOW_FILLS.load(deps.storage)?)
+        },
+    );
+    Box::new(contract)
+}
+
+#[cw_serde]
+pub enum MockDaoDaoQueryMsg {
+    ListStakers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+}
+
+#[cw_serde]
+pub struct MockStakerBalance {
+    pub address: String,
+    pub balance: Uint128,
+}
+
+#[cw_serde]
+pub struct MockListStakersResponse {
+    pub stakers: Vec<MockStakerBalance>,
+}
+
+const MOCK_DAODAO_HOOK: Item<Addr> = Item::new("mock_daodao_hook");
+const MOCK_DAODAO_STAKERS: Map<&str, Uint128> = Map::new("mock_daodao_stakers");
+
+/// A DAO DAO staking contract that tracks the stake changes it's sent, and forwards them to its hook.
+pub fn contract_mock_daodao() -> Box<dyn Contract<KujiraMsg>> {
+    let contract = ContractWrapper::new_with_empty(
+        |deps: DepsMut, _, _, msg: StakeChangedHookMsg| -> StdResult<Response> {
+            let (addr, change) = match &msg {
+                StakeChangedHookMsg::Stake { addr, amount } => (addr, Ok(*amount)),
+                StakeChangedHookMsg::Unstake { addr, amount } => (addr, Err(*amount)),
+            };
+            let staked = MOCK_DAODAO_STAKERS
+                .may_load(deps.storage, addr.as_str())?
+                .unwrap_or_default();
+            let staked = match change {
+                Ok(amount) => staked.checked_add(amount)?,
+                Err(amount) => staked.checked_sub(amount)?,
+            };
+            MOCK_DAODAO_STAKERS.save(deps.storage, addr.as_str(), &staked)?;
+            let hook = MOCK_DAODAO_HOOK.load(deps.storage)?;
+            Ok(Response::new().add_message(wasm_execute(
+                hook,
+                &ExecuteMsg::StakeChangeHook(msg),
+                vec![],
+            )?))
+        },
+        |deps: DepsMut, _, _, hook: Addr| -> StdResult<Response> {
+            MOCK_DAODAO_HOOK.save(deps.storage, &hook)?;
+            Ok(Response::default())
+        },
+        |deps: Deps, _, msg: MockDaoDaoQueryMsg| -> StdResult<Binary> {
+            let MockDaoDaoQueryMsg::ListStakers { start_after, limit } = msg;
+            let stakers = MOCK_DAODAO_STAKERS
+                .range(
+                    deps.storage,
+                    start_after.as_deref().map(Bound::exclusive),
+                    None,
+                    Order::Ascending,
+                )
+                .take(limit.unwrap_or(10) as usize)
+                .map(|item| item.map(|(address, balance)| MockStakerBalance { address, balance }))
+                .collect::<StdResult<_>>()?;
+            to_json_binary(&MockListStakersResponse { stakers })
+        },
+    );
+    Box::new(contract)
+}
+
+pub type KujiraApp =
+    App<BankKeeper, MockApi, MockStorage, KujiraModule, WasmKeeper<KujiraMsg, Empty>>;
+
+/// Handles the kujira tokenfactory messages, minting and burning through the bank module.
+pub struct KujiraModule;
+
+impl Module for KujiraModule {
+    type ExecT = KujiraMsg;
+    type QueryT = Empty;
+    type SudoT = Empty;
+
+    fn execute<ExecC, QueryC>(
+        &self,
+        api: &dyn Api,
+        storage: &mut dyn Storage,
+        router: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        block: &BlockInfo,
+        sender: Addr,
+        msg: KujiraMsg,
+    ) -> anyhow::Result<AppResponse>
+    where
+        ExecC: CustomMsg + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        match msg {
+            KujiraMsg::Denom(DenomMsg::Create { .. }) => Ok(AppResponse::default()),
+            KujiraMsg::Denom(DenomMsg::Mint {
+                denom,
+                amount,
+                recipient,
+            }) => {
+                let mint = BankSudo::Mint {
+                    to_address: recipient.to_string(),
+                    amount: coins(amount.u128(), denom.to_string()),
+                };
+                router.sudo(api, storage, block, SudoMsg::Bank(mint))
+            }
+            KujiraMsg::Denom(DenomMsg::Burn { denom, amount }) => {
+                let burn = BankMsg::Burn {
+                    amount: coins(amount.u128(), denom.to_string()),
+                };
+                router.execute(api, storage, block, sender, burn.into())
+            }
+            msg => anyhow::bail!("Unsupported kujira message: {msg:?}"),
+        }
+    }
+
+    fn query(
+        &self,
+        _: &dyn Api,
+        _: &dyn Storage,
+        _: &dyn Querier,
+        _: &BlockInfo,
+        _: Empty,
+    ) -> anyhow::Result<Binary> {
+        anyhow::bail!("Unsupported kujira query")
+    }
+
+    fn sudo<ExecC, QueryC>(
+        &self,
+        _: &dyn Api,
+        _: &mut dyn Storage,
+        _: &dyn CosmosRouter<ExecC = ExecC, QueryC = QueryC>,
+        _: &BlockInfo,
+        _: Empty,
+    ) -> anyhow::Result<AppResponse>
+    where
+        ExecC: CustomMsg + DeserializeOwned + 'static,
+        QueryC: CustomQuery + DeserializeOwned + 'static,
+    {
+        anyhow::bail!("Unsupported kujira sudo")
+    }
+}
+
+pub fn multi_app() -> KujiraApp {
+    BasicAppBuilder::<KujiraMsg, Empty>::new_custom()
+        .with_custom(KujiraModule)
+        .build(|_, _, _| {})
 }
 
 pub fn setup_test_env(
-    mut app: App,
+    mut app: KujiraApp,
     initial_balance: Vec<(&str, Vec<Coin>)>,
     instantiate_msg: InstantiateMsg,
 ) -> TestEnv {
     let owner = app.api().addr_make("owner");
     let rewards_code_id = app.store_code(contract_rewards());
     let cw4_code_id = app.store_code(contract_cw4());
+    let receiver_code_id = app.store_code(contract_mock_receiver());
+    let swap_code_id = app.store_code(contract_mock_swap());
+    let underlying_code_id = app.store_code(contract_mock_underlying());
+    let bow_code_id = app.store_code(contract_mock_bow_staking());
+    let daodao_code_id = app.store_code(contract_mock_daodao());
 
     let initial_balance = initial_balance
         .into_iter()
@@ -78,11 +367,16 @@ pub fn setup_test_env(
         rewards_addr,
         rewards_code_id,
         cw4_code_id,
+        receiver_code_id,
+        swap_code_id,
+        underlying_code_id,
+        bow_code_id,
+        daodao_code_id,
     }
 }
 
 pub fn create_config(
-    app: &App,
+    app: &KujiraApp,
     owner: &str,
     staking_module: StakingConfig,
     incentive_module: Option<IncentiveConfig>,
@@ -97,6 +391,39 @@ pub fn create_config(
         distribution_module,
         underlying_rewards_module,
         inflation_module,
+        stake_limits: None,
+        forfeit_recipient: None,
+    }
+}
+
+/// A distribution module with no fees and every denom whitelisted, for use as a base config.
+pub fn distribution_config() -> DistributionConfig {
+    DistributionConfig {
+        fees: vec![],
+        denom_fees: vec![],
+        inflation_top_up: None,
+        conversion: None,
+        stream: None,
+        whitelisted_denoms: Whitelist::All,
+    }
+}
+
+/// An incentive module with no limits, fees or whitelist, for use as a base config.
+pub fn incentive_config() -> IncentiveConfig {
+    IncentiveConfig {
+        crank_limit: 10,
+        min_size: Uint128::zero(),
+        fee: None,
+        whitelisted_denoms: Whitelist::All,
+        reward_fees: vec![],
+    }
+}
+
+/// An inflation module with a zero rate and no fees, for use as a base config.
+pub fn inflation_config() -> InflationConfig {
+    InflationConfig {
+        rate_per_year: Decimal::zero(),
+        reward_fees: vec![],
     }
 }
 
@@ -136,6 +463,7 @@ impl TestEnv {
                 amount: Uint128::new(amount),
                 callback: None,
                 withdraw_rewards: false,
+                denom: None,
             })),
             &[],
         )
@@ -280,6 +608,15 @@ impl TestEnv {
         )
     }
 
+    pub fn claim_fees(&mut self, account: &str) -> anyhow::Result<AppResponse> {
+        self.app.execute_contract(
+            self.addr(account),
+            self.rewards_addr.clone(),
+            &ExecuteMsg::ClaimFees {},
+            &[],
+        )
+    }
+
     pub fn update_config(
         &mut self,
         account: &str,
@@ -316,6 +653,107 @@ impl TestEnv {
         Ok(rewards_addr)
     }
 
+    /// Instantiates a mock swap contract, minting it enough liquidity to fill any test swap.
+    pub fn instantiate_swap(&mut self, ask_denom: &str, price: Decimal) -> Addr {
+        let msg = MockSwapInstantiateMsg {
+            ask_denom: ask_denom.to_string(),
+            price,
+        };
+        let addr = self
+            .instantiate(msg, self.swap_code_id, &format!("swap-{ask_denom}"))
+            .unwrap();
+        self.app
+            .sudo(SudoMsg::Bank(BankSudo::Mint {
+                to_address: addr.to_string(),
+                amount: coins(1_000_000_000, ask_denom),
+            }))
+            .unwrap();
+        addr
+    }
+
+    /// Instantiates a mock underlying rewards contract, minting it enough to pay out any test's claims.
+    pub fn instantiate_underlying(&mut self, reported: Vec<Coin>, paid: Vec<Coin>) -> Addr {
+        let msg = MockUnderlyingInstantiateMsg {
+            reported,
+            paid: paid.clone(),
+        };
+        let addr = self
+            .instantiate(msg, self.underlying_code_id, "mock underlying")
+            .unwrap();
+        self.app
+            .sudo(SudoMsg::Bank(BankSudo::Mint {
+                to_address: addr.to_string(),
+                amount: paid
+                    .into_iter()
+                    .map(|c| coin(c.amount.u128() * 100, c.denom))
+                    .collect(),
+            }))
+            .unwrap();
+        addr
+    }
+
+    /// Instantiates a mock BOW staking contract, with `fills` pending for its first claim.
+    pub fn instantiate_bow_staking(&mut self, fills: Vec<Coin>) -> Addr {
+        let addr = self
+            .instantiate(fills.clone(), self.bow_code_id, "mock bow staking")
+            .unwrap();
+        self.app
+            .sudo(SudoMsg::Bank(BankSudo::Mint {
+                to_address: addr.to_string(),
+                amount: fills,
+            }))
+            .unwrap();
+        addr
+    }
+
+    /// Instantiates a mock DAO DAO staking contract, hooked to the rewards contract.
+    pub fn instantiate_daodao(&mut self) -> Addr {
+        self.instantiate(
+            self.rewards_addr.clone(),
+            self.daodao_code_id,
+            "mock daodao",
+        )
+        .unwrap()
+    }
+
+    /// Adds a validator without commission, staking `ustake` at a 10% APR.
+    pub fn add_validator(&mut self, address: &str) {
+        let block = self.app.block_info();
+        self.app.init_modules(|router, api, storage| {
+            let info = StakingInfo {
+                bonded_denom: "ustake".to_string(),
+                unbonding_time: 60,
+                apr: Decimal::percent(10),
+            };
+            router.staking.setup(storage, info).unwrap();
+            let validator = Validator::create(
+                address.to_string(),
+                Decimal::zero(),
+                Decimal::one(),
+                Decimal::one(),
+            );
+            router
+                .staking
+                .add_validator(api, storage, &block, validator)
+                .unwrap();
+        });
+    }
+
+    /// Mints `amount` of `ustake` to `delegator`, and delegates it to `validator`.
+    pub fn delegate(&mut self, delegator: &Addr, validator: &str, amount: u128) {
+        self.app
+            .sudo(SudoMsg::Bank(BankSudo::Mint {
+                to_address: delegator.to_string(),
+                amount: coins(amount, "ustake"),
+            }))
+            .unwrap();
+        let msg = StakingMsg::Delegate {
+            validator: validator.to_string(),
+            amount: coin(amount, "ustake"),
+        };
+        self.app.execute(delegator.clone(), msg.into()).unwrap();
+    }
+
     pub fn query<T: DeserializeOwned>(&self, query_msg: QueryMsg) -> StdResult<T> {
         self.app
             .wrap()
@@ -16,16 +16,18 @@ mod macros {
             use cosmwasm_std::Addr;
 
             let staking_module = match stringify!($staking_variant) {
-                "NativeToken" => StakingConfig::NativeToken { denom: $staking_arg.to_string() },
+                "NativeToken" => StakingConfig::NativeToken { denom: $staking_arg.to_string(), receipt: None },
                 "DaoDaoHook" => StakingConfig::DaoDaoHook { daodao_addr: Addr::unchecked($staking_arg) },
                 "Cw4Hook" => StakingConfig::Cw4Hook { cw4_addr: Addr::unchecked($staking_arg) },
                 "Permissioned" => StakingConfig::Permissioned {},
                 _ => panic!("Invalid staking configuration"),
             };
 
+            #[allow(clippy::needless_update)]
             let incentive_module = None $(
                 .or(Some(IncentiveConfig {
                     $( $incentive_key: $incentive_value, )+
+                    ..crate::testing::test_helpers::incentive_config()
                 }))
             )?;
 
@@ -35,11 +37,13 @@ mod macros {
                 }))
             )?;
 
-            let underlying_rewards_module = None $( .or(Some(UnderlyingConfig { underlying_rewards_contract: Addr::unchecked($underlying) })) )?;
+            let underlying_rewards_module = None $( .or(Some(UnderlyingConfig { sources: vec![cw_rewards_logic::underlying::UnderlyingSource::CwRewards { contract: Addr::unchecked($underlying) }], forward_stake_to: None, reward_fees: vec![] })) )?;
 
+            #[allow(clippy::needless_update)]
             let inflation_module = None $(
                 .or(Some(InflationConfig {
                     $( $inflation_key: $inflation_value, )+
+                    ..crate::testing::test_helpers::inflation_config()
                 }))
             )?;
 
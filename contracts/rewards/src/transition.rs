@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{
+    coin, ensure, Addr, BankMsg, CosmosMsg, Decimal, Order, StdResult, Storage, Uint128,
+};
+use kujira::KujiraMsg;
+
+use crate::{
+    contract::STATE_MACHINE, delegation, msg::StakingConfig, principal, slash, Config,
+    ContractError,
+};
+
+/// The most stakers a switch converts, refunds or reweights in one transaction, so it fits in a block.
+/// Above it, stakers have to unstake before the switch.
+pub const MAX_MIGRATED_STAKERS: usize = 100;
+
+/// Moves the stake held under the current staking module over to `update`.
+///
+/// Stake held in the contract is converted where the new module can hold the same tokens, refunded to
+/// its stakers where the new module holds no tokens, and otherwise the switch is rejected. Weights from
+/// a new hook source are resynced separately, once the config is saved.
+pub fn migrate(
+    storage: &mut dyn Storage,
+    config: &Config,
+    update: &StakingConfig,
+) -> Result<Vec<CosmosMsg<KujiraMsg>>, ContractError> {
+    use StakingConfig::*;
+
+    let forwarded = config
+        .underlying_rewards_module
+        .as_ref()
+        .and_then(|cfg| cfg.forward_stake_to.as_ref())
+        .is_some();
+
+    match (&config.staking_module, update) {
+        (NativeToken { denom: old, .. }, NativeToken { denom: new, .. }) if old == new => {
+            Ok(vec![])
+        }
+        (NativeTokens { denoms: old }, NativeTokens { denoms }) => {
+            reweight(storage, old, denoms)?;
+            Ok(vec![])
+        }
+        (Delegated { denom: old, .. }, Delegated { denom: new, .. }) if old == new => Ok(vec![]),
+        (current, _) if !holds_stake(storage, current)? => Ok(vec![]),
+        (Cw4Hook { .. } | DaoDaoHook { .. } | Permissioned {}, Cw4Hook { .. })
+        | (Cw4Hook { .. } | DaoDaoHook { .. } | Permissioned {}, DaoDaoHook { .. })
+        | (Cw4Hook { .. } | DaoDaoHook { .. } | Permissioned {}, Permissioned {}) => Ok(vec![]),
+        (NativeToken { denom, .. }, NativeTokens { denoms }) if !forwarded => {
+            convert_to_denoms(storage, denom, denoms)?;
+            Ok(vec![])
+        }
+        (
+            NativeToken { denom, .. },
+            Delegated {
+                denom: delegated,
+                validators,
+                ..
+            },
+        ) if !forwarded && denom == delegated => {
+            let mut staked = Uint128::zero();
+            for (staker, amount) in take_stakes(storage)? {
+                STATE_MACHINE.set_weight(storage, &staker, amount, false)?;
+                staked = staked.checked_add(amount)?;
+            }
+            Ok(delegation::delegate(validators, denom, staked))
+        }
+        (NativeToken { denom, .. }, Cw4Hook { .. } | DaoDaoHook { .. } | Permissioned {})
+            if !forwarded =>
+        {
+            refund(storage, denom)
+        }
+        (NativeTokens { .. }, Cw4Hook { .. } | DaoDaoHook { .. } | Permissioned {}) => {
+            refund_denoms(storage)
+        }
+        (current, update) => Err(ContractError::UnsafeStakingMigration(
+            name(current),
+            name(update),
+        )),
+    }
+}
+
+/// Whether anything is staked, or still owed to stakers, under `staking`.
+fn holds_stake(storage: &dyn Storage, staking: &StakingConfig) -> Result<bool, ContractError> {
+    let held = match staking {
+        StakingConfig::NativeTokens { .. } => principal::any(storage)?,
+        StakingConfig::Delegated { .. } => delegation::any_unbonding(storage)?,
+        _ => false,
+    };
+    Ok(held || !STATE_MACHINE.total_staked(storage)?.is_zero())
+}
+
+/// Every staker's weight, as long as there are at most [`MAX_MIGRATED_STAKERS`] of them.
+fn weights(storage: &dyn Storage) -> Result<Vec<(String, Uint128)>, ContractError> {
+    let weights = STATE_MACHINE
+        .user_weights
+        .range(storage, None, None, Order::Ascending)
+        .take(MAX_MIGRATED_STAKERS + 1)
+        .collect::<Result<Vec<_>, _>>()?;
+    ensure!(
+        weights.len() <= MAX_MIGRATED_STAKERS,
+        ContractError::TooManyStakers(MAX_MIGRATED_STAKERS)
+    );
+    Ok(weights.into_iter().filter(|(_, w)| !w.is_zero()).collect())
+}
+
+/// Weights each staker's principal at the new multipliers. Denoms can only be dropped once nothing is
+/// staked in them.
+fn reweight(
+    storage: &mut dyn Storage,
+    old: &[(String, Decimal)],
+    denoms: &[(String, Decimal)],
+) -> Result<(), ContractError> {
+    for (denom, _) in old {
+        if !denoms.iter().any(|(d, _)| d == denom) {
+            ensure!(
+                principal::total(storage, denom)?.is_zero(),
+                ContractError::DenomStaked(denom.clone())
+            );
+        }
+    }
+    for (staker, _) in weights(storage)? {
+        let weight = principal::weight(storage, &Addr::unchecked(&staker), denoms)?;
+        STATE_MACHINE.set_weight(storage, &staker, weight, false)?;
+    }
+    Ok(())
+}
+
+/// Each staker's NativeToken stake in tokens, with weights no longer counting slashed shares.
+fn take_stakes(storage: &mut dyn Storage) -> Result<Vec<(String, Uint128)>, ContractError> {
+    let stakes = weights(storage)?
+        .into_iter()
+        .map(|(staker, weight)| Ok((staker, slash::to_amount(storage, weight)?)))
+        .collect::<StdResult<Vec<_>>>()?;
+    slash::reset(storage);
+    Ok(stakes)
+}
+
+/// Records each staker's NativeToken stake as principal in `denom`, weighted at its new multiplier.
+fn convert_to_denoms(
+    storage: &mut dyn Storage,
+    denom: &str,
+    denoms: &[(String, Decimal)],
+) -> Result<(), ContractError> {
+    if !denoms.iter().any(|(d, _)| d == denom) {
+        return Err(ContractError::UnsafeStakingMigration(
+            "NativeToken",
+            "NativeTokens",
+        ));
+    }
+    for (staker, amount) in take_stakes(storage)? {
+        let addr = Addr::unchecked(&staker);
+        principal::add(storage, &addr, &coin(amount.u128(), denom))?;
+        let weight = principal::weight(storage, &addr, denoms)?;
+        STATE_MACHINE.set_weight(storage, &staker, weight, false)?;
+    }
+    Ok(())
+}
+
+/// Returns each staker's NativeToken stake, keeping their accrued rewards claimable.
+fn refund(
+    storage: &mut dyn Storage,
+    denom: &str,
+) -> Result<Vec<CosmosMsg<KujiraMsg>>, ContractError> {
+    let mut msgs = vec![];
+    for (staker, amount) in take_stakes(storage)? {
+        STATE_MACHINE.set_weight(storage, &staker, Uint128::zero(), false)?;
+        msgs.push(
+            BankMsg::Send {
+                to_address: staker,
+                amount: vec![coin(amount.u128(), denom)],
+            }
+            .into(),
+        );
+    }
+    Ok(msgs)
+}
+
+/// Returns each staker's NativeTokens principal, keeping their accrued rewards claimable.
+fn refund_denoms(storage: &mut dyn Storage) -> Result<Vec<CosmosMsg<KujiraMsg>>, ContractError> {
+    for (staker, _) in weights(storage)? {
+        STATE_MACHINE.set_weight(storage, &staker, Uint128::zero(), false)?;
+    }
+    let mut refunds = BTreeMap::<Addr, Vec<_>>::new();
+    for (staker, principal) in principal::drain(storage)? {
+        refunds.entry(staker).or_default().push(principal);
+    }
+    Ok(refunds
+        .into_iter()
+        .map(|(staker, amount)| {
+            BankMsg::Send {
+                to_address: staker.to_string(),
+                amount,
+            }
+            .into()
+        })
+        .collect())
+}
+
+pub fn name(staking: &StakingConfig) -> &'static str {
+    match staking {
+        StakingConfig::NativeToken { .. } => "NativeToken",
+        StakingConfig::NativeTokens { .. } => "NativeTokens",
+        StakingConfig::Cw4Hook { .. } => "Cw4Hook",
+        StakingConfig::DaoDaoHook { .. } => "DaoDaoHook",
+        StakingConfig::Permissioned {} => "Permissioned",
+        StakingConfig::Delegated { .. } => "Delegated",
+    }
+}
@@ -0,0 +1,106 @@
+use cosmwasm_std::{
+    Addr, Coin, Decimal, DepsMut, Env, Event, MessageInfo, QuerierWrapper, Response, StdResult,
+    Storage, SubMsg,
+};
+use cw_rewards_logic::{fee, underlying::UnderlyingSource, RewardsMsg};
+use cw_storage_plus::Item;
+use cw_utils::NativeBalance;
+use kujira::KujiraMsg;
+
+use crate::{
+    contract::STATE_MACHINE,
+    delegation,
+    msg::StakingConfig,
+    snapshot::{self, Measured},
+    Config, ContractError,
+};
+
+/// The configured underlying sources, plus the contract's own delegations under Delegated staking.
+pub fn sources(
+    querier: &QuerierWrapper,
+    env: &Env,
+    config: &Config,
+) -> StdResult<Vec<UnderlyingSource>> {
+    let mut sources = config
+        .underlying_rewards_module
+        .as_ref()
+        .map(|cfg| cfg.sources.clone())
+        .unwrap_or_default();
+    if let StakingConfig::Delegated { denom, .. } = &config.staking_module {
+        for source in delegation::reward_sources(querier, &env.contract.address, denom)? {
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+    }
+    Ok(sources)
+}
+
+/// Protocol fees on rewards from `source`. Only configured sources pay the underlying `reward_fees`.
+pub fn reward_fees<'a>(config: &'a Config, source: &UnderlyingSource) -> &'a [(Decimal, Addr)] {
+    match &config.underlying_rewards_module {
+        Some(cfg) if cfg.sources.contains(source) => &cfg.reward_fees,
+        _ => &[],
+    }
+}
+
+/// Claims pending rewards from each underlying source.
+///
+/// Claimed rewards are only distributed once they arrive, as measured in the claim's reply, so an
+/// underlying source can't credit stakers with rewards it never pays out. The rewards message that
+/// triggered the claims should be deferred until then, so it still sees the claimed rewards.
+pub fn claim(
+    deps: &mut DepsMut,
+    env: &Env,
+    sources: &[UnderlyingSource],
+) -> Result<Vec<SubMsg<KujiraMsg>>, ContractError> {
+    let mut claims = vec![];
+    for source in sources.iter() {
+        let pending = source.pending_rewards(&deps.querier, &env.contract.address)?;
+        if pending.is_empty() {
+            continue;
+        }
+
+        let measured = Measured::Claim {
+            source: source.clone(),
+            denoms: pending.into_iter().map(|c| c.denom).collect(),
+        };
+        claims.push(snapshot::measure(deps, env, measured, source.claim_msg()?)?);
+    }
+
+    Ok(claims)
+}
+
+/// The rewards message that triggered the pending claims, run once they're all distributed.
+const DEFERRED: Item<(MessageInfo, RewardsMsg)> = Item::new("underlying_deferred");
+
+pub fn defer(storage: &mut dyn Storage, info: &MessageInfo, msg: &RewardsMsg) -> StdResult<()> {
+    DEFERRED.save(storage, &(info.clone(), msg.clone()))
+}
+
+/// Claims made outside of a rewards message, such as before a rebalance, have nothing deferred.
+pub fn take_deferred(storage: &mut dyn Storage) -> StdResult<Option<(MessageInfo, RewardsMsg)>> {
+    let deferred = DEFERRED.may_load(storage)?;
+    DEFERRED.remove(storage);
+    Ok(deferred)
+}
+
+/// Distributes the measured rewards claimed from `source`, after accruing protocol fees.
+pub fn distribute_claimed(
+    deps: DepsMut,
+    source: UnderlyingSource,
+    mut rewards: Vec<Coin>,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    let config = Config::load(deps.storage)?;
+    fee::accrue(deps.storage, &mut rewards, reward_fees(&config, &source))?;
+    if !rewards.is_empty() {
+        STATE_MACHINE.distribute_rewards(deps.storage, &rewards)?;
+    }
+
+    let event = Event::new("rewards/claim-underlying").add_attributes(vec![
+        ("action", "rewards/claim-underlying"),
+        ("source", &source.to_string()),
+        ("rewards", &NativeBalance(rewards).to_string()),
+    ]);
+    Ok(Response::default().add_event(event))
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeSet;
+
+use cosmwasm_std::{ensure, Addr, DepsMut, Event, MessageInfo, Response, Uint128};
+use kujira::KujiraMsg;
+
+use crate::{
+    contract::STATE_MACHINE,
+    managers,
+    msg::{Role, StakingConfig},
+    roles, Config, ContractError,
+};
+
+/// How a batch of weights applies to each staker's current weight.
+#[derive(Clone, Copy)]
+pub enum Op {
+    Set,
+    Increase,
+    Decrease,
+}
+
+impl Op {
+    fn action(&self) -> &'static str {
+        match self {
+            Op::Set => "rewards/set-weights",
+            Op::Increase => "rewards/increase-weights",
+            Op::Decrease => "rewards/decrease-weights",
+        }
+    }
+
+    fn apply(&self, current: Uint128, amount: Uint128) -> Option<Uint128> {
+        match self {
+            Op::Set => Some(amount),
+            Op::Increase => current.checked_add(amount).ok(),
+            Op::Decrease => current.checked_sub(amount).ok(),
+        }
+    }
+}
+
+/// Applies a batch of weights under Permissioned staking, with an event per staker. The whole batch is
+/// validated first, so a bad entry fails it, naming the offending address.
+///
+/// A [`Role::WeightAdmin`]'s batches apply to stakers' weights as a whole, while a registered weight
+/// manager's apply to the slice of each weight that it granted, within its quota. A slice is capped at the
+/// staker's current weight, releasing the rest of the quota, in case an admin has since lowered it.
+pub fn execute(
+    deps: DepsMut,
+    info: MessageInfo,
+    config: Config,
+    msg: &'static str,
+    op: Op,
+    weights: Vec<(Addr, Uint128)>,
+) -> Result<Response<KujiraMsg>, ContractError> {
+    ensure!(
+        matches!(config.staking_module, StakingConfig::Permissioned {}),
+        ContractError::InvalidStakingConfig(msg, config.staking_module)
+    );
+    let is_admin = roles::has_role(deps.storage, &config, Role::WeightAdmin, &info.sender)?;
+    let mut manager = match is_admin {
+        true => None,
+        false => Some(
+            managers::load(deps.storage, &info.sender)?.ok_or(ContractError::Unauthorized {})?,
+        ),
+    };
+
+    let mut seen = BTreeSet::new();
+    let mut updates = vec![];
+    for (addr, amount) in weights.iter() {
+        let invalid = |reason| ContractError::InvalidWeightUpdate(addr.to_string(), reason);
+        deps.api
+            .addr_validate(addr.as_str())
+            .map_err(|_| invalid("invalid address"))?;
+        ensure!(seen.insert(addr), invalid("listed more than once"));
+        ensure!(
+            matches!(op, Op::Set) || !amount.is_zero(),
+            invalid("zero change")
+        );
+
+        let current = STATE_MACHINE
+            .user_weights
+            .may_load(deps.storage, &addr.to_string())?
+            .unwrap_or_default();
+        let weight = match manager.as_mut() {
+            None => op
+                .apply(current, *amount)
+                .ok_or(invalid("decrease exceeds weight"))?,
+            Some(manager) => {
+                if let Some(stakers) = &manager.quota.stakers {
+                    ensure!(stakers.contains(addr), invalid("not managed by sender"));
+                }
+                let recorded = managers::granted(deps.storage, &info.sender, addr)?;
+                let granted = recorded.min(current);
+                let slice = op
+                    .apply(granted, *amount)
+                    .ok_or(invalid("decrease exceeds granted weight"))?;
+                manager.used = (manager.used.checked_add(slice)?)
+                    .checked_sub(recorded)
+                    .map_err(|_| invalid("decrease exceeds granted weight"))?;
+                if let Some(max_weight) = manager.quota.max_weight {
+                    ensure!(
+                        manager.used <= max_weight,
+                        invalid("exceeds the manager's weight budget")
+                    );
+                }
+                managers::grant(deps.storage, &info.sender, addr, slice)?;
+                (current.checked_add(slice)?)
+                    .checked_sub(granted)
+                    .map_err(|_| invalid("decrease exceeds weight"))?
+            }
+        };
+        updates.push((addr.to_string(), *amount, current, weight));
+    }
+    if let Some(manager) = manager {
+        managers::save(deps.storage, &info.sender, &manager)?;
+    }
+
+    let mut events = vec![];
+    for (staker, amount, current, weight) in updates {
+        if weight > current {
+            STATE_MACHINE.increase_weight(deps.storage, &staker, weight - current, false)?;
+        } else if weight < current {
+            STATE_MACHINE.decrease_weight(deps.storage, &staker, current - weight, false)?;
+        }
+        events.push(Event::new(op.action()).add_attributes(vec![
+            ("action", op.action()),
+            ("sender", info.sender.as_str()),
+            ("staker", &staker),
+            ("amount", &amount.to_string()),
+            ("weight", &weight.to_string()),
+        ]));
+    }
+
+    Ok(Response::default().add_events(events))
+}
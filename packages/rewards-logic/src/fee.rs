@@ -0,0 +1,70 @@
+use cosmwasm_std::{Addr, Coin, Decimal, StdResult, Storage};
+use cw_storage_plus::Map;
+use cw_utils::NativeBalance;
+
+use crate::util::calculate_fee_split;
+
+/// Protocol fees accrued to each recipient, pending withdrawal.
+pub const ACCRUED_FEES: Map<&Addr, Vec<Coin>> = Map::new("accrued_fees");
+
+/// Takes the fees out of `rewards` in place, and accrues them to their recipients.
+pub fn accrue(
+    storage: &mut dyn Storage,
+    rewards: &mut Vec<Coin>,
+    fees: &[(Decimal, Addr)],
+) -> StdResult<()> {
+    for (addr, coins) in calculate_fee_split(rewards, fees) {
+        ACCRUED_FEES.update(storage, &addr, |existing| -> StdResult<_> {
+            let mut balance = NativeBalance(existing.unwrap_or_default()) + NativeBalance(coins);
+            balance.normalize();
+            Ok(balance.into_vec())
+        })?;
+    }
+    Ok(())
+}
+
+pub fn accrued(storage: &dyn Storage, addr: &Addr) -> StdResult<Vec<Coin>> {
+    Ok(ACCRUED_FEES.may_load(storage, addr)?.unwrap_or_default())
+}
+
+/// Withdraws all fees accrued to `addr`.
+pub fn claim(storage: &mut dyn Storage, addr: &Addr) -> StdResult<Vec<Coin>> {
+    let fees = accrued(storage, addr)?;
+    ACCRUED_FEES.remove(storage, addr);
+    Ok(fees)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{coin, testing::mock_dependencies};
+
+    #[test]
+    fn test_accrue_and_claim() {
+        let mut deps = mock_dependencies();
+        let fees = vec![
+            (Decimal::percent(10), Addr::unchecked("test1")),
+            (Decimal::percent(20), Addr::unchecked("test2")),
+        ];
+
+        let mut rewards = vec![coin(1000, "token1")];
+        accrue(deps.as_mut().storage, &mut rewards, &fees).unwrap();
+        assert_eq!(rewards, vec![coin(700, "token1")]);
+
+        let mut rewards = vec![coin(1000, "token1"), coin(1000, "token2")];
+        accrue(deps.as_mut().storage, &mut rewards, &fees).unwrap();
+        assert_eq!(rewards, vec![coin(700, "token1"), coin(700, "token2")]);
+
+        let test1 = Addr::unchecked("test1");
+        assert_eq!(
+            accrued(deps.as_ref().storage, &test1).unwrap(),
+            vec![coin(200, "token1"), coin(100, "token2")]
+        );
+        assert_eq!(
+            claim(deps.as_mut().storage, &test1).unwrap(),
+            vec![coin(200, "token1"), coin(100, "token2")]
+        );
+        assert!(accrued(deps.as_ref().storage, &test1).unwrap().is_empty());
+        assert!(claim(deps.as_mut().storage, &test1).unwrap().is_empty());
+    }
+}
@@ -1,10 +1,10 @@
-use crate::RewardsSM;
+use crate::{fee, util::calculate_fee_split, RewardsSM};
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{coin, Coin, StdResult, Storage, Timestamp, Uint128};
-use cw_storage_plus::{Index, IndexList, IndexedMap, Item, MultiIndex};
+use cosmwasm_std::{coin, Addr, Coin, Decimal, StdResult, Storage, Timestamp, Uint128};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 use kujira::{
     bow::staking::{IncentiveResponse, ScheduleResponse},
-    Schedule,
+    Release, Schedule,
 };
 
 pub fn incentives<'a>() -> IndexedMap<u128, Incentive, IncentiveIndexes<'a>> {
@@ -33,12 +33,18 @@ impl<'a> IndexList<Incentive> for IncentiveIndexes<'a> {
 
 pub const INCENTIVE_ID: Item<Uint128> = Item::new("incentive_id");
 
+/// The running stream incentive of each denom.
+const STREAMS: Map<&str, Uint128> = Map::new("incentive_streams");
+
 #[cw_serde]
 pub struct Incentive {
     pub id: Uint128,
     pub denom: String,
     pub schedule: Schedule,
     pub last_distributed: Timestamp,
+    /// Streamed direct distributions are exempt from incentive reward fees.
+    #[serde(default)]
+    pub stream: bool,
 }
 
 impl Incentive {
@@ -59,6 +65,7 @@ impl Incentive {
             denom,
             schedule,
             last_distributed: *now,
+            stream: false,
         })
     }
 
@@ -104,29 +111,95 @@ pub fn load_incentives(storage: &dyn Storage, limit: usize) -> StdResult<Vec<Inc
         .take(limit)
         .collect::<StdResult<Vec<_>>>()
 }
+/// Streams `rewards` linearly over `duration` seconds from `now`, as one incentive per denom. What's
+/// left of a denom's running stream is folded into the new one, so streams don't pile up.
+pub fn stream(
+    storage: &mut dyn Storage,
+    rewards: &[Coin],
+    now: &Timestamp,
+    duration: u64,
+) -> StdResult<()> {
+    for reward in rewards {
+        let mut amount = reward.amount;
+        if let Some(id) = STREAMS.may_load(storage, &reward.denom)? {
+            if let Some(running) = incentives().may_load(storage, id.u128())? {
+                amount += running
+                    .schedule
+                    .released(&running.last_distributed, &running.schedule.end);
+                incentives().remove(storage, id.u128())?;
+            }
+        }
+        let schedule = Schedule {
+            start: *now,
+            end: now.plus_seconds(duration),
+            amount,
+            release: Release::Fixed,
+        };
+        let mut incentive = Incentive::new(storage, reward.denom.clone(), schedule, now)?;
+        incentive.stream = true;
+        incentive.save(storage)?;
+        STREAMS.save(storage, &reward.denom, &incentive.id)?;
+    }
+    Ok(())
+}
+
 pub fn distribute_lri(
     storage: &mut dyn Storage,
     limit: usize,
     sm: RewardsSM,
     now: &Timestamp,
+) -> StdResult<Vec<Coin>> {
+    distribute_lri_with_fees(storage, limit, sm, now, &[])
+}
+
+/// Distributes released incentives, after accruing `fees` on all but streams. Returns the amount
+/// distributed to stakers.
+pub fn distribute_lri_with_fees(
+    storage: &mut dyn Storage,
+    limit: usize,
+    sm: RewardsSM,
+    now: &Timestamp,
+    fees: &[(Decimal, Addr)],
 ) -> StdResult<Vec<Coin>> {
     let mut rewards = vec![];
+    let mut streamed = vec![];
     for mut incentive in load_incentives(storage, limit)? {
         if let Some(reward) = incentive.distribute(now) {
-            rewards.push(reward);
+            match incentive.stream {
+                true => streamed.push(reward),
+                false => rewards.push(reward),
+            }
         }
         incentive.save(storage)?;
     }
+    fee::accrue(storage, &mut rewards, fees)?;
+    rewards.extend(streamed);
     sm.distribute_rewards(storage, &rewards)?;
     Ok(rewards)
 }
 
 pub fn get_lri(storage: &dyn Storage, limit: usize, now: &Timestamp) -> StdResult<Vec<Coin>> {
+    get_lri_with_fees(storage, limit, now, &[])
+}
+
+/// Released incentives that would be distributed to stakers, net of `fees` on all but streams.
+pub fn get_lri_with_fees(
+    storage: &dyn Storage,
+    limit: usize,
+    now: &Timestamp,
+    fees: &[(Decimal, Addr)],
+) -> StdResult<Vec<Coin>> {
     let mut rewards = vec![];
+    let mut streamed = vec![];
     for mut incentive in load_incentives(storage, limit)? {
         if let Some(reward) = incentive.distribute(now) {
-            rewards.push(reward);
+            match incentive.stream {
+                true => streamed.push(reward),
+                false => rewards.push(reward),
+            }
         }
     }
+    calculate_fee_split(&mut rewards, fees);
+    rewards.extend(streamed);
     Ok(rewards)
 }
@@ -1,9 +1,14 @@
-use cosmwasm_std::{coin, ensure, Coin, Decimal, StdError, StdResult, Storage, Timestamp, Uint128};
+use cosmwasm_std::{
+    coin, ensure, Addr, Coin, Decimal, Decimal256, StdError, StdResult, Storage, Timestamp,
+    Uint128, Uint64,
+};
 use cw_storage_plus::Item;
 
-use crate::RewardsSM;
+use crate::{fee, RewardsSM};
 
-const YEAR_SECONDS: u64 = 365 * 24 * 60 * 60;
+pub const DAY_SECONDS: u64 = 24 * 60 * 60;
+pub const WEEK_SECONDS: u64 = 7 * DAY_SECONDS;
+pub const YEAR_SECONDS: u64 = 365 * DAY_SECONDS;
 
 pub const LAST_INFLATION_UPDATE: Item<Timestamp> = Item::new("last_inflation_update");
 pub const INFLATION_FUNDS: Item<Coin> = Item::new("inflation_funds");
@@ -13,13 +18,22 @@ pub fn pending_inflation(
     sm: &RewardsSM,
     rate: &Decimal,
     now: &Timestamp,
+) -> StdResult<Option<(Coin, Coin)>> {
+    pending_inflation_on(storage, sm.total_staked(storage)?, rate, now)
+}
+
+/// Pending inflation, emitted on `staked` tokens rather than the total stake weight, for when the two
+/// differ.
+pub fn pending_inflation_on(
+    storage: &dyn Storage,
+    staked: Uint128,
+    rate: &Decimal,
+    now: &Timestamp,
 ) -> StdResult<Option<(Coin, Coin)>> {
     let last_update = LAST_INFLATION_UPDATE.may_load(storage)?;
     if let Some(last_update) = last_update {
         let seconds = now.seconds() - last_update.seconds();
-        let total_staked = sm.total_staked(storage)?;
-        let mut inflation_amount =
-            total_staked.mul_floor(rate * Decimal::from_ratio(seconds, YEAR_SECONDS));
+        let mut inflation_amount = emission(staked, rate, seconds);
 
         let mut funds_left = match INFLATION_FUNDS.may_load(storage)? {
             Some(coin) => coin,
@@ -38,16 +52,61 @@ pub fn pending_inflation(
     }
 }
 
+/// The amount of inflation emitted over `seconds` for the given `total_staked`, ignoring funding.
+pub fn emission(total_staked: Uint128, rate: &Decimal, seconds: u64) -> Uint128 {
+    total_staked.mul_floor(rate * Decimal::from_ratio(seconds, YEAR_SECONDS))
+}
+
+/// Returns the time at which `funds` will be exhausted at the current `total_staked`.
+///
+/// Returns `None` if nothing is being emitted, or if the runway overflows the representable time range.
+pub fn exhaustion_time(
+    total_staked: Uint128,
+    rate: &Decimal,
+    funds: Uint128,
+    now: &Timestamp,
+) -> Option<Timestamp> {
+    if total_staked.is_zero() || rate.is_zero() {
+        return None;
+    }
+    let seconds = Decimal256::checked_from_ratio(funds, total_staked)
+        .ok()?
+        .checked_div(Decimal256::from(*rate))
+        .ok()?
+        .checked_mul(Decimal256::from_ratio(YEAR_SECONDS, 1u64))
+        .ok()?
+        .to_uint_floor();
+    let seconds = Uint64::try_from(seconds).ok()?.u64();
+    seconds
+        .checked_mul(1_000_000_000)
+        .and_then(|nanos| now.nanos().checked_add(nanos))
+        .map(Timestamp::from_nanos)
+}
+
 pub fn crank(
     storage: &mut dyn Storage,
     sm: RewardsSM,
     rate: &Decimal,
     now: &Timestamp,
 ) -> StdResult<Vec<Coin>> {
-    let pending = pending_inflation(storage, &sm, rate, now)?;
+    let staked = sm.total_staked(storage)?;
+    crank_with_fees(storage, sm, staked, rate, now, &[])
+}
+
+/// Distributes pending inflation emitted on `staked` tokens, after accruing `fees`. Returns the amount
+/// distributed to stakers.
+pub fn crank_with_fees(
+    storage: &mut dyn Storage,
+    sm: RewardsSM,
+    staked: Uint128,
+    rate: &Decimal,
+    now: &Timestamp,
+    fees: &[(Decimal, Addr)],
+) -> StdResult<Vec<Coin>> {
+    let pending = pending_inflation_on(storage, staked, rate, now)?;
     LAST_INFLATION_UPDATE.save(storage, now)?;
 
-    let (inflation, remaining_left) = match pending {
+    let (mut inflation, remaining_left) = match pending {
         Some((inflation, remaining_left)) => {
             if inflation.amount.is_zero() {
                 return Ok(vec![]);
@@ -57,6 +116,7 @@ pub fn crank(
         None => return Ok(vec![]),
     };
 
+    fee::accrue(storage, &mut inflation, fees)?;
     sm.distribute_rewards(storage, &inflation)?;
 
     if remaining_left.amount.is_zero() {
@@ -68,7 +128,20 @@ pub fn crank(
     Ok(inflation)
 }
 
+/// Whether `denom` can be added to the inflation pool, which only holds one denom.
+pub fn accepts(storage: &dyn Storage, denom: &str) -> StdResult<bool> {
+    Ok(match INFLATION_FUNDS.may_load(storage)? {
+        Some(funds) => funds.denom == denom,
+        None => true,
+    })
+}
+
+/// Adds `funds` to the inflation pool. Used both for owner funding and for internal credits,
+/// such as top-ups routed from distributions and incentive fees.
 pub fn fund(storage: &mut dyn Storage, funds: Coin) -> StdResult<()> {
+    if funds.amount.is_zero() {
+        return Ok(());
+    }
     match INFLATION_FUNDS.may_load(storage)? {
         Some(mut existing) => {
             ensure!(
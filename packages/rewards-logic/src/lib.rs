@@ -1,8 +1,10 @@
 pub mod execute;
+pub mod fee;
 pub mod incentive;
 pub mod inflation;
 pub mod query;
 pub mod state_machine;
+pub mod underlying;
 pub mod util;
 
 mod error;
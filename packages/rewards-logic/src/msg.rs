@@ -1,5 +1,5 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Coin, Uint128};
+use cosmwasm_std::{Addr, BankMsg, Binary, Coin, CosmosMsg, Empty, StdResult, Uint128, WasmMsg};
 use kujira::CallbackData;
 
 #[cw_serde]
@@ -25,6 +25,10 @@ pub struct UnstakeMsg {
     pub amount: Uint128,
     pub withdraw_rewards: bool,
     pub callback: Option<CallbackData>,
+    /// The denom to unstake, on contracts staking several denoms. Can be omitted if the staker only has
+    /// one of them staked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub denom: Option<String>,
 }
 
 #[cw_serde]
@@ -78,3 +82,57 @@ pub enum RewardsQueryMsg {
 pub struct PendingRewardsResponse {
     pub rewards: Vec<Coin>,
 }
+
+/// How a fee is delivered to a contract recipient.
+#[cw_serde]
+pub enum FeeAction {
+    /// Executes the recipient with `msg`, attaching the fee as funds.
+    Execute { msg: Binary },
+    /// Sends the fee to the recipient as a kujira callback.
+    Callback(CallbackData),
+}
+
+/// Recipient of a fee. A plain address receives the fee with a [`BankMsg::Send`].
+#[cw_serde]
+#[serde(untagged)]
+pub enum FeeRecipient {
+    Addr(Addr),
+    Action { addr: Addr, action: FeeAction },
+}
+
+impl FeeRecipient {
+    pub fn addr(&self) -> &Addr {
+        match self {
+            FeeRecipient::Addr(addr) | FeeRecipient::Action { addr, .. } => addr,
+        }
+    }
+
+    pub fn to_message<T>(&self, amount: Vec<Coin>) -> StdResult<CosmosMsg<T>> {
+        match self {
+            FeeRecipient::Addr(addr) => Ok(BankMsg::Send {
+                to_address: addr.to_string(),
+                amount,
+            }
+            .into()),
+            FeeRecipient::Action {
+                addr,
+                action: FeeAction::Execute { msg },
+            } => Ok(WasmMsg::Execute {
+                contract_addr: addr.to_string(),
+                msg: msg.clone(),
+                funds: amount,
+            }
+            .into()),
+            FeeRecipient::Action {
+                addr,
+                action: FeeAction::Callback(cb),
+            } => cb.to_message(addr, Empty {}, amount),
+        }
+    }
+}
+
+impl From<Addr> for FeeRecipient {
+    fn from(addr: Addr) -> Self {
+        FeeRecipient::Addr(addr)
+    }
+}
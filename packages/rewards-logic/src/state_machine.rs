@@ -200,6 +200,50 @@ impl<'a> RewardsSM<'a> {
         Ok(accrued)
     }
 
+    /// Remove the reward weight of the specified user without settling their rewards.
+    ///
+    /// Rewards since the user's last update are forfeited, and left undistributed. Rewards already accrued
+    /// are kept.
+    ///
+    /// Returns the removed weight, and the forfeited rewards. Rewards that can't be calculated, such as
+    /// those that overflow, are left out.
+    pub fn forfeit_weight(
+        &self,
+        storage: &mut dyn Storage,
+        user: &String,
+    ) -> StdResult<(Uint128, Vec<Coin>)> {
+        let cur_weight = self
+            .user_weights
+            .may_load(storage, user)?
+            .unwrap_or_default();
+
+        let mut forfeited = vec![];
+        for item in self
+            .global_indices
+            .range(storage, None, None, Order::Ascending)
+        {
+            let (denom, index) = item?;
+            let last_index = self
+                .user_rewards
+                .may_load(storage, (user, &denom))?
+                .map_or(Decimal256::zero(), |info| info.last_index);
+            let amount = index
+                .checked_sub(last_index)
+                .ok()
+                .and_then(|delta| Uint256::from(cur_weight).checked_mul_floor(delta).ok())
+                .and_then(|amount| Uint128::try_from(amount).ok());
+            if let Some(amount) = amount {
+                forfeited.push(coin(amount.u128(), denom));
+            }
+        }
+
+        self.user_weights.remove(storage, user);
+        self.total_staked.update(storage, |cur| -> StdResult<_> {
+            Ok(cur.checked_sub(cur_weight)?)
+        })?;
+        Ok((cur_weight, normalize(forfeited)))
+    }
+
     /// Claim the accrued rewards for the specified user, setting the accrued rewards to zero.
     pub fn claim_accrued(&self, storage: &mut dyn Storage, user: &String) -> StdResult<Vec<Coin>> {
         let cur_weight = self
@@ -756,4 +800,41 @@ mod test {
         assert_eq!(ret[1].denom, "ucoin");
         assert_eq!(ret[1].amount.u128(), 200u128);
     }
+
+    #[test]
+    fn forfeit_weight() {
+        let mut odeps = mock_dependencies();
+        let state = RewardsSM::new();
+        let deps = odeps.as_mut();
+        state.initialize(deps.storage).expect("initialize works");
+
+        let user = "user".to_string();
+        state
+            .increase_weight(deps.storage, &user, 100u128.into(), false)
+            .expect("increase works");
+        state
+            .distribute_rewards(deps.storage, &coins(100u128, "ucoin"))
+            .unwrap();
+        state
+            .add_accrued_rewards(deps.storage, &user, &coins(50u128, "ucoin"))
+            .expect("add works");
+        let (weight, forfeited) = state
+            .forfeit_weight(deps.storage, &user)
+            .expect("forfeit works");
+        assert_eq!(weight.u128(), 100u128);
+        assert_eq!(forfeited, coins(100u128, "ucoin"));
+        assert!(!state.user_weights.has(deps.storage, &user));
+        assert!(state.total_staked.load(deps.storage).unwrap().is_zero());
+
+        // Only the rewards accrued before are kept
+        let ret = state.get_accrued(deps.storage, &user).expect("get works");
+        assert_eq!(ret, coins(50u128, "ucoin"));
+
+        // Staking again starts from the current index
+        state
+            .increase_weight(deps.storage, &user, 100u128.into(), false)
+            .expect("increase works");
+        let ret = state.get_accrued(deps.storage, &user).expect("get works");
+        assert_eq!(ret, coins(50u128, "ucoin"));
+    }
 }
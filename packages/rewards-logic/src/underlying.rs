@@ -0,0 +1,97 @@
+use std::fmt;
+
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_json_binary, Addr, Coin, CosmosMsg, CustomMsg, DistributionMsg, QuerierWrapper, StdResult,
+    WasmMsg,
+};
+use kujira::{bow::staking as bow, Denom};
+
+use crate::{ClaimRewardsMsg, RewardsMsg};
+
+/// A source of rewards earned upstream, along with how to query and claim them.
+#[cw_serde]
+pub enum UnderlyingSource {
+    /// A cw-rewards contract.
+    CwRewards { contract: Addr },
+    /// A Kujira BOW staking contract, for the rewards on `denom` stake.
+    BowStaking { contract: Addr, denom: Denom },
+    /// Native x/distribution rewards on a delegation to `validator`.
+    Delegation { validator: String },
+}
+
+/// The subset of the cw-rewards query interface used to query pending rewards.
+#[cw_serde]
+enum CwRewardsQuery {
+    PendingRewards { staker: Addr },
+}
+
+impl UnderlyingSource {
+    /// Rewards `staker` can currently claim from this source.
+    pub fn pending_rewards(&self, querier: &QuerierWrapper, staker: &Addr) -> StdResult<Vec<Coin>> {
+        let mut rewards = match self {
+            UnderlyingSource::CwRewards { contract } => {
+                let res: crate::PendingRewardsResponse = querier.query_wasm_smart(
+                    contract,
+                    &CwRewardsQuery::PendingRewards {
+                        staker: staker.clone(),
+                    },
+                )?;
+                res.rewards
+            }
+            UnderlyingSource::BowStaking { contract, denom } => querier.query_wasm_smart(
+                contract,
+                &bow::QueryMsg::Fills {
+                    denom: denom.clone(),
+                    addr: staker.clone(),
+                },
+            )?,
+            UnderlyingSource::Delegation { validator } => querier
+                .query_delegation(staker, validator)?
+                .map(|d| d.accumulated_rewards)
+                .unwrap_or_default(),
+        };
+        rewards.retain(|c| !c.amount.is_zero());
+        Ok(rewards)
+    }
+
+    /// The message that claims this source's pending rewards to the sender.
+    pub fn claim_msg<T: CustomMsg>(&self) -> StdResult<CosmosMsg<T>> {
+        Ok(match self {
+            UnderlyingSource::CwRewards { contract } => WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: to_json_binary(&RewardsMsg::ClaimRewards(ClaimRewardsMsg {
+                    callback: None,
+                }))?,
+                funds: vec![],
+            }
+            .into(),
+            UnderlyingSource::BowStaking { contract, denom } => WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: to_json_binary(&bow::ExecuteMsg::Claim {
+                    denom: denom.clone(),
+                })?,
+                funds: vec![],
+            }
+            .into(),
+            UnderlyingSource::Delegation { validator } => {
+                DistributionMsg::WithdrawDelegatorReward {
+                    validator: validator.clone(),
+                }
+                .into()
+            }
+        })
+    }
+}
+
+impl fmt::Display for UnderlyingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnderlyingSource::CwRewards { contract } => write!(f, "cw-rewards:{contract}"),
+            UnderlyingSource::BowStaking { contract, denom } => {
+                write!(f, "bow-staking:{contract}:{denom}")
+            }
+            UnderlyingSource::Delegation { validator } => write!(f, "delegation:{validator}"),
+        }
+    }
+}
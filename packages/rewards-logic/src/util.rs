@@ -1,16 +1,18 @@
-use cosmwasm_std::{Addr, BankMsg, Coin, CosmosMsg, CustomMsg, Decimal, Uint128};
+use cosmwasm_std::{Coin, CosmosMsg, CustomMsg, Decimal, StdResult, Uint128};
 
-pub fn calculate_total_fee(reward: &Uint128, fees: &[(Decimal, Addr)]) -> Uint128 {
+use crate::FeeRecipient;
+
+pub fn calculate_total_fee<R>(reward: &Uint128, fees: &[(Decimal, R)]) -> Uint128 {
     fees.iter().fold(Uint128::zero(), |acc, (fee, _)| {
         acc + reward.mul_floor(*fee)
     })
 }
 
-/// Calculates the fees sent to each address and modifies input in place.
-pub fn calculate_fee_split(
+/// Calculates the fees sent to each recipient and modifies input in place.
+pub fn calculate_fee_split<R: Clone>(
     rewards: &mut Vec<Coin>,
-    fees: &[(Decimal, Addr)],
-) -> Vec<(Addr, Vec<Coin>)> {
+    fees: &[(Decimal, R)],
+) -> Vec<(R, Vec<Coin>)> {
     let mut result = Vec::with_capacity(fees.len());
     fees.iter().for_each(|(_, addr)| {
         result.push((addr.clone(), Vec::with_capacity(rewards.len())));
@@ -35,11 +37,71 @@ pub fn calculate_fee_split(
     result
 }
 
+/// Calculates the fees sent to each recipient and modifies input in place.
+///
+/// Each denom uses its schedule in `denom_fees` if it has one, falling back to `fees` otherwise.
+/// Fees for the same recipient are merged.
+pub fn calculate_denom_fee_split<R: Clone + PartialEq>(
+    rewards: &mut Vec<Coin>,
+    fees: &[(Decimal, R)],
+    denom_fees: &[(String, Vec<(Decimal, R)>)],
+) -> Vec<(R, Vec<Coin>)> {
+    let mut result: Vec<(R, Vec<Coin>)> = Vec::with_capacity(fees.len());
+    for Coin { denom, amount } in rewards.iter_mut() {
+        let schedule = denom_fees
+            .iter()
+            .find(|(d, _)| d == denom)
+            .map_or(fees, |(_, fees)| fees.as_slice());
+
+        let mut total_fee = Uint128::zero();
+        for (fee, recipient) in schedule {
+            let fee_amt = amount.mul_floor(*fee);
+            if fee_amt.is_zero() {
+                continue;
+            }
+            total_fee += fee_amt;
+
+            let addr_rewards = match result.iter().position(|(r, _)| r == recipient) {
+                Some(idx) => &mut result[idx].1,
+                None => {
+                    result.push((recipient.clone(), Vec::with_capacity(1)));
+                    &mut result.last_mut().unwrap().1
+                }
+            };
+            match addr_rewards.iter_mut().find(|c| &c.denom == denom) {
+                Some(existing) => existing.amount += fee_amt,
+                None => addr_rewards.push(Coin {
+                    denom: denom.clone(),
+                    amount: fee_amt,
+                }),
+            }
+        }
+        *amount -= total_fee;
+    }
+    rewards.retain(|c| !c.amount.is_zero());
+
+    result
+}
+
+/// Takes `share` of the `denom` reward out of `rewards`, modifying input in place. Nothing is taken if
+/// `share` is more than the whole reward.
+pub fn calculate_share(rewards: &mut Vec<Coin>, denom: &str, share: Decimal) -> Option<Coin> {
+    let reward = rewards.iter_mut().find(|c| c.denom == denom)?;
+    let amount = reward.amount.mul_floor(share);
+    reward.amount = reward.amount.checked_sub(amount).ok()?;
+    rewards.retain(|c| !c.amount.is_zero());
+
+    (!amount.is_zero()).then(|| Coin {
+        denom: denom.to_string(),
+        amount,
+    })
+}
+
 /// Splits the entire input amount among the recipients according to their relative weights.
-pub fn calculate_fee_distribution(
+pub fn calculate_fee_distribution<R: Clone>(
     rewards: Vec<Coin>,
-    fees: &[(Decimal, Addr)],
-) -> Vec<(Addr, Vec<Coin>)> {
+    fees: &[(Decimal, R)],
+) -> Vec<(R, Vec<Coin>)> {
     let mut result = Vec::with_capacity(fees.len());
     let mut total_weight = Decimal::zero();
 
@@ -67,25 +129,23 @@ pub fn calculate_fee_distribution(
     result
 }
 
-pub fn calculate_fee_msgs<T: CustomMsg>(fees: Vec<(Addr, Vec<Coin>)>) -> Vec<CosmosMsg<T>> {
+/// Builds the messages delivering each fee to its recipient, see [`FeeRecipient::to_message`].
+pub fn calculate_fee_msgs<T: CustomMsg, R: Into<FeeRecipient>>(
+    fees: Vec<(R, Vec<Coin>)>,
+) -> StdResult<Vec<CosmosMsg<T>>> {
     let mut msgs = Vec::with_capacity(fees.len());
-    for (addr, coins) in fees {
+    for (recipient, coins) in fees {
         if !coins.is_empty() {
-            msgs.push(
-                BankMsg::Send {
-                    to_address: addr.into(),
-                    amount: coins,
-                }
-                .into(),
-            );
+            msgs.push(recipient.into().to_message(coins)?);
         }
     }
-    msgs
+    Ok(msgs)
 }
 #[cfg(test)]
 mod tests {
     use super::*;
-    use cosmwasm_std::{coin, Addr, Decimal};
+    use crate::FeeAction;
+    use cosmwasm_std::{coin, Addr, BankMsg, Binary, Decimal, WasmMsg};
 
     #[test]
     fn test_basic_fee_split() {
@@ -217,6 +277,173 @@ mod tests {
         assert_eq!(rewards, vec![coin(500, "token1")]);
     }
 
+    #[test]
+    fn test_denom_fee_split_duplicate_recipient() {
+        let mut rewards = vec![coin(1000, "token1")];
+        let fees = vec![
+            (Decimal::percent(10), Addr::unchecked("test1")),
+            (Decimal::percent(20), Addr::unchecked("test1")),
+        ];
+
+        let result = calculate_denom_fee_split(&mut rewards, &fees, &[]);
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].1, vec![coin(300, "token1")]);
+        assert_eq!(rewards, vec![coin(700, "token1")]);
+    }
+
+    #[test]
+    fn test_denom_fee_split() {
+        let mut rewards = vec![
+            coin(1000, "native"),
+            coin(1000, "partner"),
+            coin(1000, "other"),
+        ];
+        let fees = vec![(Decimal::percent(10), Addr::unchecked("test1"))];
+        let denom_fees = vec![
+            ("native".to_string(), vec![]),
+            (
+                "partner".to_string(),
+                vec![(Decimal::percent(5), Addr::unchecked("test2"))],
+            ),
+        ];
+
+        let result = calculate_denom_fee_split(&mut rewards, &fees, &denom_fees);
+
+        assert_eq!(result[0].0, Addr::unchecked("test2"));
+        assert_eq!(result[0].1, vec![coin(50, "partner")]);
+
+        assert_eq!(result[1].0, Addr::unchecked("test1"));
+        assert_eq!(result[1].1, vec![coin(100, "other")]);
+
+        assert_eq!(
+            rewards,
+            vec![
+                coin(1000, "native"),
+                coin(950, "partner"),
+                coin(900, "other")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_denom_fee_split_shared_recipient() {
+        let mut rewards = vec![coin(1000, "token1"), coin(1000, "token2")];
+        let fees = vec![
+            (Decimal::percent(10), Addr::unchecked("test1")),
+            (Decimal::percent(10), Addr::unchecked("test2")),
+        ];
+        let denom_fees = vec![(
+            "token2".to_string(),
+            vec![(Decimal::percent(50), Addr::unchecked("test2"))],
+        )];
+
+        let result = calculate_denom_fee_split(&mut rewards, &fees, &denom_fees);
+
+        assert_eq!(result[0].0, Addr::unchecked("test1"));
+        assert_eq!(result[0].1, vec![coin(100, "token1")]);
+
+        assert_eq!(result[1].0, Addr::unchecked("test2"));
+        assert_eq!(result[1].1, vec![coin(100, "token1"), coin(500, "token2")]);
+
+        assert_eq!(rewards, vec![coin(800, "token1"), coin(500, "token2")]);
+    }
+
+    #[test]
+    fn test_denom_fee_split_without_overrides() {
+        let mut rewards = vec![coin(1000, "token1"), coin(1000, "token2")];
+        let fees = vec![(Decimal::percent(10), Addr::unchecked("test1"))];
+
+        let result = calculate_denom_fee_split(&mut rewards, &fees, &[]);
+
+        assert_eq!(
+            result,
+            calculate_fee_split(&mut vec![coin(1000, "token1"), coin(1000, "token2")], &fees)
+        );
+        assert_eq!(rewards, vec![coin(900, "token1"), coin(900, "token2")]);
+    }
+
+    #[test]
+    fn test_share() {
+        let mut rewards = vec![coin(1000, "token1"), coin(1000, "token2")];
+
+        let result = calculate_share(&mut rewards, "token1", Decimal::percent(10));
+
+        assert_eq!(result, Some(coin(100, "token1")));
+        assert_eq!(rewards, vec![coin(900, "token1"), coin(1000, "token2")]);
+    }
+
+    #[test]
+    fn test_share_missing_denom() {
+        let mut rewards = vec![coin(1000, "token1")];
+
+        let result = calculate_share(&mut rewards, "token2", Decimal::percent(10));
+
+        assert_eq!(result, None);
+        assert_eq!(rewards, vec![coin(1000, "token1")]);
+    }
+
+    #[test]
+    fn test_full_share() {
+        let mut rewards = vec![coin(1000, "token1"), coin(1000, "token2")];
+
+        let result = calculate_share(&mut rewards, "token1", Decimal::percent(100));
+
+        assert_eq!(result, Some(coin(1000, "token1")));
+        assert_eq!(rewards, vec![coin(1000, "token2")]);
+    }
+
+    #[test]
+    fn test_share_above_one() {
+        let mut rewards = vec![coin(1000, "token1")];
+
+        let result = calculate_share(&mut rewards, "token1", Decimal::percent(101));
+
+        assert_eq!(result, None);
+        assert_eq!(rewards, vec![coin(1000, "token1")]);
+    }
+
+    #[test]
+    fn test_fee_msgs() {
+        let mut rewards = vec![coin(1000, "token1")];
+        let fees = vec![
+            (
+                Decimal::percent(10),
+                FeeRecipient::Addr(Addr::unchecked("test1")),
+            ),
+            (
+                Decimal::percent(20),
+                FeeRecipient::Action {
+                    addr: Addr::unchecked("test2"),
+                    action: FeeAction::Execute {
+                        msg: Binary::from(b"{\"deposit\":{}}"),
+                    },
+                },
+            ),
+        ];
+
+        let result = calculate_fee_split(&mut rewards, &fees);
+        let msgs: Vec<CosmosMsg> = calculate_fee_msgs(result).unwrap();
+
+        assert_eq!(rewards, vec![coin(700, "token1")]);
+        assert_eq!(
+            msgs,
+            vec![
+                BankMsg::Send {
+                    to_address: "test1".to_string(),
+                    amount: vec![coin(100, "token1")],
+                }
+                .into(),
+                WasmMsg::Execute {
+                    contract_addr: "test2".to_string(),
+                    msg: Binary::from(b"{\"deposit\":{}}"),
+                    funds: vec![coin(200, "token1")],
+                }
+                .into(),
+            ]
+        );
+    }
+
     #[test]
     fn test_distribution_equal() {
         let rewards = vec![coin(1000, "token1")];